@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
+use crate::hardware::message::usb::{LoRaProperties, USBLocalPayloadRFControl};
+
 #[derive(Parser)]
 #[command(author, version, about, long_about)]
 pub struct Cli {
@@ -12,8 +14,27 @@ pub struct Cli {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppConfig {
+    #[serde(default)]
+    pub transport: TransportKind,
     pub database: DataBase,
     pub port: Vec<String>,
+    pub radio: Option<RadioConfig>,
+    pub state_file: Option<PathBuf>,
+    #[serde(default)]
+    pub wrench: WrenchConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    pub http: Option<HttpConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Redis,
+    /// reader_uri/writer_uri 形如 mqtt://host:1883?client_id=wrench, 队列名作为主题
+    /// 以 client_id 保留会话, 订阅端和发布端需要使用各自固定不变的 client_id
+    Mqtt,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -22,4 +43,189 @@ pub struct DataBase {
     pub writer_queue: String,
     pub reader_uri: String,
     pub writer_uri: String,
+    /// 配置后使用 Redis Streams 代替发布/订阅
+    pub stream: Option<StreamConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub group: String,
+    pub consumer: String,
+    pub field: String,
+    /// 发布时使用 XADD MAXLEN ~ 限制流的近似长度, 为 0 时不限制
+    pub max_len: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            group: "wrench".to_string(),
+            consumer: "wrench".to_string(),
+            field: "message".to_string(),
+            max_len: 100000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RadioConfig {
+    pub freq_hz: u32,
+    pub txpower: u8,
+    pub lora: LoRaProperties,
+}
+
+impl RadioConfig {
+    /// 网关回读的射频参数是否与配置一致
+    pub fn matches(&self, rf_control: &USBLocalPayloadRFControl) -> bool {
+        rf_control.freq_hz == self.freq_hz
+            && rf_control.txpower == self.txpower
+            && rf_control.lora == self.lora
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WrenchConfig {
+    pub request_timeout_ms: u64,
+    pub max_retransmits: u8,
+    pub clock_check_interval_secs: u64,
+    pub clock_drift_threshold_secs: u32,
+    pub network_check_interval_secs: u64,
+    pub network_history_len: usize,
+    /// 历史记录中较新一半与较早一半的平均 RSSI 相差超过该值时告警
+    pub rssi_drop_threshold_db: i16,
+    /// 相邻两次查询之间 CRC 错误增加超过该值时告警
+    pub crc_error_threshold: u16,
+    /// 未充电时电池电压低于该值发出低电量告警
+    pub low_voltage_mv: u16,
+    pub critical_voltage_mv: u16,
+    /// 扳手处于工作状态时查询拧紧数据的间隔
+    pub joint_poll_working_ms: u64,
+    /// 空闲时的查询间隔, 没有新数据时逐步加倍直到 joint_poll_max_ms
+    pub joint_poll_idle_ms: u64,
+    pub joint_poll_max_ms: u64,
+    /// 单次 GetJointData 请求的最大数量, 受限于一个数据包的长度
+    pub joint_batch_size: u8,
+    /// 重连后收取断线期间数据的最长时间, 超时后直接重新下发任务
+    pub reconnect_drain_timeout_secs: u64,
+    /// 每把扳手保留的已结束任务数量, 超出后丢弃最早的任务
+    pub finished_task_limit: usize,
+    /// 每个螺栓完成之前累计不合格的最大次数, 为 0 时不限制, 任务中的 maxNokCount 优先
+    pub max_nok_per_bolt: u16,
+    pub nok_action: ReworkAction,
+    /// 扳手序列号 (十六进制) 前缀到型号的映射, 按最长的前缀匹配
+    pub wrench_models: HashMap<String, String>,
+    /// 按型号匹配的放电曲线
+    pub battery_curves: Vec<BatteryCurve>,
+    /// 型号未知或没有对应曲线时使用该型号的曲线, 为空时不估算电量
+    pub fallback_battery_model: Option<String>,
+}
+
+/// 螺栓不合格次数达到上限后对任务的处理
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReworkAction {
+    /// 锁定任务, 之后的结果不再计入, 只能取消任务
+    #[default]
+    Lock,
+    /// 中止任务并开始下一个任务
+    Abort,
+    /// 锁定任务直到主管解除
+    Release,
+}
+
+impl ReworkAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReworkAction::Lock => "lock",
+            ReworkAction::Abort => "abort",
+            ReworkAction::Release => "release",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatteryCurve {
+    pub model: String,
+    /// (电压 mV, 电量百分比)
+    pub points: Vec<(u16, u8)>,
+    /// 满电时可以完成的拧紧次数
+    pub joints_per_charge: u32,
+}
+
+impl Default for WrenchConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_ms: 3000,
+            max_retransmits: 3,
+            clock_check_interval_secs: 600,
+            clock_drift_threshold_secs: 2,
+            network_check_interval_secs: 60,
+            network_history_len: 30,
+            rssi_drop_threshold_db: 10,
+            crc_error_threshold: 20,
+            low_voltage_mv: 3500,
+            critical_voltage_mv: 3300,
+            joint_poll_working_ms: 1000,
+            joint_poll_idle_ms: 5000,
+            joint_poll_max_ms: 60000,
+            joint_batch_size: 16,
+            reconnect_drain_timeout_secs: 30,
+            finished_task_limit: 50,
+            max_nok_per_bolt: 0,
+            nok_action: ReworkAction::Lock,
+            wrench_models: HashMap::new(),
+            battery_curves: vec![BatteryCurve {
+                model: "default".to_string(),
+                points: vec![
+                    (3300, 0),
+                    (3500, 10),
+                    (3650, 30),
+                    (3750, 50),
+                    (3900, 75),
+                    (4100, 95),
+                    (4200, 100),
+                ],
+                joints_per_charge: 1000,
+            }],
+            fallback_battery_model: Some("default".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// 没有配置 token 时只允许监听回环地址
+    pub listen: String,
+    /// 配置后请求需要携带 Authorization: Bearer <token>
+    pub token: Option<String>,
+    pub max_body_bytes: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8080".to_string(),
+            token: None,
+            max_body_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OutboxConfig {
+    pub path: Option<PathBuf>,
+    pub max_messages: usize,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_messages: 10000,
+        }
+    }
 }
@@ -0,0 +1,209 @@
+use crate::{
+    app_data::{BatteryCurve, WrenchConfig},
+    hardware::message::wrc::WRCPayloadInfoEnergy,
+};
+
+/// 电压回升超过阈值该值后才解除低电量告警, 避免在阈值附近反复告警
+const VOLTAGE_HYSTERESIS_MV: u16 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BatteryLevel {
+    #[default]
+    Normal,
+    Low,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnergyEvent {
+    Charging(bool),
+    Hibernated(bool),
+    PowerConnected(bool),
+    BatteryLevel(BatteryLevel, u16),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnergyState {
+    pub reported: bool,
+    pub charging: bool,
+    pub hibernated: bool,
+    pub power_connected: bool,
+    pub battery_level: BatteryLevel,
+}
+
+impl EnergyState {
+    /// 根据新的能量信息更新状态, 返回发生变化的事件
+    pub fn update(
+        &mut self,
+        info: &WRCPayloadInfoEnergy,
+        low_voltage_mv: u16,
+        critical_voltage_mv: u16,
+    ) -> Vec<EnergyEvent> {
+        let mut events = vec![];
+
+        let charging = info.flag.is_charging();
+        let hibernated = info.flag.is_hibernated();
+        let power_connected = info.flag.is_power_connected();
+        // 首次上报只记录状态, 不作为状态切换
+        if self.reported {
+            if charging != self.charging {
+                events.push(EnergyEvent::Charging(charging));
+            }
+            if hibernated != self.hibernated {
+                events.push(EnergyEvent::Hibernated(hibernated));
+            }
+            if power_connected != self.power_connected {
+                events.push(EnergyEvent::PowerConnected(power_connected));
+            }
+        }
+        self.reported = true;
+        self.charging = charging;
+        self.hibernated = hibernated;
+        self.power_connected = power_connected;
+
+        let voltage = info.battery_voltage_mv;
+        let level = if charging || power_connected {
+            BatteryLevel::Normal
+        } else if voltage <= critical_voltage_mv {
+            BatteryLevel::Critical
+        } else if voltage <= low_voltage_mv {
+            BatteryLevel::Low
+        } else if voltage <= low_voltage_mv.saturating_add(VOLTAGE_HYSTERESIS_MV) {
+            self.battery_level.min(BatteryLevel::Low)
+        } else {
+            BatteryLevel::Normal
+        };
+        // 电量变低时告警, 回到正常时通知恢复
+        if level > self.battery_level
+            || (level == BatteryLevel::Normal && self.battery_level != BatteryLevel::Normal)
+        {
+            events.push(EnergyEvent::BatteryLevel(level, voltage));
+        }
+        self.battery_level = level;
+
+        events
+    }
+}
+
+impl BatteryCurve {
+    /// 按照放电曲线在相邻两点之间线性插值估算电量百分比
+    pub fn percent(&self, voltage_mv: u16) -> Option<u8> {
+        let mut points = self.points.clone();
+        points.sort_by_key(|(mv, _)| *mv);
+
+        let (first, last) = (points.first()?, points.last()?);
+        if voltage_mv <= first.0 {
+            return Some(first.1.min(100));
+        }
+        if voltage_mv >= last.0 {
+            return Some(last.1.min(100));
+        }
+
+        let upper = points.iter().position(|(mv, _)| *mv >= voltage_mv)?;
+        let (low_mv, low_percent) = points[upper - 1];
+        let (high_mv, high_percent) = points[upper];
+        let percent = low_percent as i32
+            + (voltage_mv - low_mv) as i32 * (high_percent as i32 - low_percent as i32)
+                / (high_mv - low_mv) as i32;
+
+        Some(percent.clamp(0, 100) as u8)
+    }
+
+    pub fn remaining_joints(&self, percent: u8) -> u32 {
+        self.joints_per_charge * percent as u32 / 100
+    }
+}
+
+impl WrenchConfig {
+    /// 按序列号前缀确定扳手型号
+    pub fn wrench_model(&self, serial: u128) -> Option<&str> {
+        let serial = format!("{:X}", serial);
+        self.wrench_models
+            .iter()
+            .filter(|(prefix, _)| serial.starts_with(&prefix.to_uppercase()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, model)| model.as_str())
+    }
+
+    /// 使用扳手型号对应的放电曲线, 没有时使用 fallback_battery_model 的曲线
+    pub fn battery_curve(&self, serial: u128) -> Option<&BatteryCurve> {
+        let find = |model: &str| self.battery_curves.iter().find(|c| c.model == model);
+        self.wrench_model(serial)
+            .and_then(find)
+            .or_else(|| self.fallback_battery_model.as_deref().and_then(find))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatteryLevel, EnergyEvent, EnergyState};
+    use crate::{
+        app_data::{BatteryCurve, WrenchConfig},
+        hardware::message::wrc::{WRCPayloadInfoEnergy, WRCPayloadInfoEnergyFlag},
+    };
+
+    fn energy(voltage: u16, charging: bool) -> WRCPayloadInfoEnergy {
+        let mut flag = WRCPayloadInfoEnergyFlag(0);
+        flag.set_charging(charging);
+        WRCPayloadInfoEnergy {
+            flag,
+            battery_voltage_mv: voltage,
+        }
+    }
+
+    #[test]
+    fn energy_event_test() {
+        let mut state = EnergyState::default();
+        assert!(state.update(&energy(3900, false), 3500, 3300).is_empty());
+        assert_eq!(
+            state.update(&energy(3450, false), 3500, 3300),
+            vec![EnergyEvent::BatteryLevel(BatteryLevel::Low, 3450)]
+        );
+        // 在回差范围内不会恢复也不会重复告警
+        assert!(state.update(&energy(3550, false), 3500, 3300).is_empty());
+        assert_eq!(
+            state.update(&energy(3250, false), 3500, 3300),
+            vec![EnergyEvent::BatteryLevel(BatteryLevel::Critical, 3250)]
+        );
+        assert_eq!(
+            state.update(&energy(3300, true), 3500, 3300),
+            vec![
+                EnergyEvent::Charging(true),
+                EnergyEvent::BatteryLevel(BatteryLevel::Normal, 3300)
+            ]
+        );
+    }
+
+    #[test]
+    fn battery_curve_test() {
+        let curve = BatteryCurve {
+            model: "".to_string(),
+            points: vec![(4200, 100), (3300, 0), (3700, 40)],
+            joints_per_charge: 500,
+        };
+        assert_eq!(curve.percent(3000), Some(0));
+        assert_eq!(curve.percent(3500), Some(20));
+        assert_eq!(curve.percent(3950), Some(70));
+        assert_eq!(curve.percent(4300), Some(100));
+        assert_eq!(curve.remaining_joints(70), 350);
+
+        let mut config = WrenchConfig::default();
+        config.battery_curves.push(BatteryCurve {
+            model: "WX200".to_string(),
+            ..curve
+        });
+        config
+            .wrench_models
+            .insert("a1".to_string(), "WX100".to_string());
+        config
+            .wrench_models
+            .insert("A1B2".to_string(), "WX200".to_string());
+        assert_eq!(config.wrench_model(0xA1B2C3), Some("WX200"));
+        assert_eq!(config.battery_curve(0xA1B2C3).unwrap().model, "WX200");
+        // 型号没有对应的曲线时使用默认曲线
+        assert_eq!(config.wrench_model(0xA1C3), Some("WX100"));
+        assert_eq!(config.battery_curve(0xA1C3).unwrap().model, "default");
+        config.fallback_battery_model = None;
+        assert!(config.battery_curve(0xC3).is_none());
+    }
+}
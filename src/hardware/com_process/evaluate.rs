@@ -0,0 +1,340 @@
+use std::fmt::Display;
+
+use crate::hardware::message::wrc::WRCJointDataMode;
+
+use super::wrench::{scale_down, JointTask};
+
+/// torque_angle_start 的精度为 0.1, 扭矩的精度为 0.001
+const SNUG_TORQUE_SCALE: i32 = 100;
+
+impl From<u8> for WRCJointDataMode {
+    fn from(mode: u8) -> Self {
+        match mode {
+            0 => WRCJointDataMode::Torque,
+            1 => WRCJointDataMode::Angle,
+            2 => WRCJointDataMode::TorqueAngle,
+            // 未知的模式与之前一样同时检查扭矩和角度
+            _ => WRCJointDataMode::AngleTorque,
+        }
+    }
+}
+
+/// 单一模式中按监控窗口检查的量, 与控制量相反
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Monitored {
+    Torque,
+    Angle,
+}
+
+impl Monitored {
+    fn name(&self) -> &'static str {
+        match self {
+            Monitored::Torque => "扭矩",
+            Monitored::Angle => "角度",
+        }
+    }
+
+    fn scale(&self) -> i32 {
+        match self {
+            Monitored::Torque => 3,
+            Monitored::Angle => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TorqueLow {
+        value: i32,
+        limit: i32,
+    },
+    TorqueHigh {
+        value: i32,
+        limit: i32,
+    },
+    AngleLow {
+        value: i16,
+        limit: i16,
+    },
+    AngleHigh {
+        value: i16,
+        limit: i16,
+    },
+    SnugTorque {
+        value: i32,
+        limit: i32,
+    },
+    MonitorLow {
+        monitored: Monitored,
+        value: i32,
+        limit: i32,
+    },
+    MonitorHigh {
+        monitored: Monitored,
+        value: i32,
+        limit: i32,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::TorqueLow { value, limit } => write!(
+                f,
+                "扭矩 {} 低于下限 {}",
+                scale_down(*value, 3),
+                scale_down(*limit, 3)
+            ),
+            Violation::TorqueHigh { value, limit } => write!(
+                f,
+                "扭矩 {} 超过上限 {}",
+                scale_down(*value, 3),
+                scale_down(*limit, 3)
+            ),
+            Violation::AngleLow { value, limit } => write!(
+                f,
+                "角度 {} 低于下限 {}",
+                scale_down(*value as i32, 1),
+                scale_down(*limit as i32, 1)
+            ),
+            Violation::AngleHigh { value, limit } => write!(
+                f,
+                "角度 {} 超过上限 {}",
+                scale_down(*value as i32, 1),
+                scale_down(*limit as i32, 1)
+            ),
+            Violation::SnugTorque { value, limit } => write!(
+                f,
+                "扭矩 {} 未达到贴合扭矩 {}",
+                scale_down(*value, 3),
+                scale_down(*limit, 3)
+            ),
+            Violation::MonitorLow {
+                monitored,
+                value,
+                limit,
+            } => write!(
+                f,
+                "监控{} {} 低于监控下限 {}",
+                monitored.name(),
+                scale_down(*value, monitored.scale()),
+                scale_down(*limit, monitored.scale())
+            ),
+            Violation::MonitorHigh {
+                monitored,
+                value,
+                limit,
+            } => write!(
+                f,
+                "监控{} {} 超过监控上限 {}",
+                monitored.name(),
+                scale_down(*value, monitored.scale()),
+                scale_down(*limit, monitored.scale())
+            ),
+        }
+    }
+}
+
+fn check_torque(task: &JointTask, torque: i32, violations: &mut Vec<Violation>) {
+    let lower = task.torque.saturating_sub(task.torque_lower_tol);
+    let upper = task.torque.saturating_add(task.torque_upper_tol);
+    if torque < lower {
+        violations.push(Violation::TorqueLow {
+            value: torque,
+            limit: lower,
+        });
+    } else if torque > upper {
+        violations.push(Violation::TorqueHigh {
+            value: torque,
+            limit: upper,
+        });
+    }
+}
+
+fn check_angle(task: &JointTask, angle: i16, violations: &mut Vec<Violation>) {
+    let lower = task.angle.saturating_sub(task.angle_lower_tol);
+    let upper = task.angle.saturating_add(task.angle_upper_tol);
+    if angle < lower {
+        violations.push(Violation::AngleLow {
+            value: angle,
+            limit: lower,
+        });
+    } else if angle > upper {
+        violations.push(Violation::AngleHigh {
+            value: angle,
+            limit: upper,
+        });
+    }
+}
+
+/// 监控量低于 monitor 或高于 target 时不合格, 两个边界分别检查, 没有配置的一侧不限制
+fn check_monitor(
+    task: &JointTask,
+    monitored: Monitored,
+    value: i32,
+    violations: &mut Vec<Violation>,
+) {
+    if let Some(min) = task.monitor_min {
+        if value < min {
+            violations.push(Violation::MonitorLow {
+                monitored,
+                value,
+                limit: min,
+            });
+            return;
+        }
+    }
+    if let Some(max) = task.monitor_max {
+        if value > max {
+            violations.push(Violation::MonitorHigh {
+                monitored,
+                value,
+                limit: max,
+            });
+        }
+    }
+}
+
+/// 按照控制模式检查一次拧紧结果, 返回所有不满足的限制, 为空时合格
+///
+/// 单一模式中控制量按目标值和公差检查, 另一个量按 monitor 和 target 的监控窗口检查;
+/// 组合模式中两个量都需要满足, 扭矩-角度模式还要求达到贴合扭矩
+pub fn evaluate(task: &JointTask, torque: i32, angle: i16) -> Vec<Violation> {
+    let mut violations = vec![];
+
+    match WRCJointDataMode::from(task.control_mode) {
+        WRCJointDataMode::Torque => {
+            check_torque(task, torque, &mut violations);
+            check_monitor(task, Monitored::Angle, angle as i32, &mut violations);
+        }
+        WRCJointDataMode::Angle => {
+            check_angle(task, angle, &mut violations);
+            check_monitor(task, Monitored::Torque, torque, &mut violations);
+        }
+        WRCJointDataMode::TorqueAngle => {
+            let snug = task.torque_angle_start.saturating_mul(SNUG_TORQUE_SCALE);
+            if torque < snug {
+                violations.push(Violation::SnugTorque {
+                    value: torque,
+                    limit: snug,
+                });
+            }
+            check_angle(task, angle, &mut violations);
+            check_torque(task, torque, &mut violations);
+        }
+        WRCJointDataMode::AngleTorque => {
+            check_torque(task, torque, &mut violations);
+            check_angle(task, angle, &mut violations);
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, Monitored, Violation};
+    use crate::hardware::com_process::wrench::JointTask;
+
+    fn task(control_mode: u8, monitor_min: Option<i32>, monitor_max: Option<i32>) -> JointTask {
+        JointTask {
+            torque: 20000,
+            torque_angle_start: 50,
+            torque_upper_tol: 1000,
+            torque_lower_tol: 1000,
+            angle: 900,
+            angle_upper_tol: 50,
+            angle_lower_tol: 50,
+            task_repeat_times: 1,
+            bolt_num: 1,
+            control_mode,
+            work_mode: 0,
+            unit: 0,
+            monitor_min,
+            monitor_max,
+            max_nok: 0,
+            nok_action: Default::default(),
+        }
+    }
+
+    #[test]
+    fn evaluate_test() {
+        // 没有配置监控窗口时扭矩模式只检查扭矩
+        assert!(evaluate(&task(0, None, None), 20500, 0).is_empty());
+        // 监控窗口按 monitor 和 target 的值检查, 与角度的目标值和公差无关
+        assert!(evaluate(&task(0, Some(100), Some(500)), 20500, 300).is_empty());
+        assert_eq!(
+            evaluate(&task(0, Some(100), Some(500)), 20500, 50),
+            vec![Violation::MonitorLow {
+                monitored: Monitored::Angle,
+                value: 50,
+                limit: 100
+            }]
+        );
+        assert_eq!(
+            evaluate(&task(0, Some(100), Some(500)), 20500, 600),
+            vec![Violation::MonitorHigh {
+                monitored: Monitored::Angle,
+                value: 600,
+                limit: 500
+            }]
+        );
+        assert!(evaluate(&task(0, Some(100), None), 20500, 3000).is_empty());
+        // 只配置 target 时仍然检查上限
+        assert!(evaluate(&task(0, None, Some(500)), 20500, 300).is_empty());
+        assert_eq!(
+            evaluate(&task(0, None, Some(500)), 20500, 600),
+            vec![Violation::MonitorHigh {
+                monitored: Monitored::Angle,
+                value: 600,
+                limit: 500
+            }]
+        );
+        assert_eq!(
+            evaluate(&task(1, None, None), 0, 960),
+            vec![Violation::AngleHigh {
+                value: 960,
+                limit: 950
+            }]
+        );
+        assert_eq!(
+            evaluate(&task(1, Some(5000), None), 4000, 900),
+            vec![Violation::MonitorLow {
+                monitored: Monitored::Torque,
+                value: 4000,
+                limit: 5000
+            }]
+        );
+        assert_eq!(
+            evaluate(&task(2, None, None), 4000, 900),
+            vec![
+                Violation::SnugTorque {
+                    value: 4000,
+                    limit: 5000
+                },
+                Violation::TorqueLow {
+                    value: 4000,
+                    limit: 19000
+                }
+            ]
+        );
+        assert!(evaluate(&task(3, None, None), 21000, 850).is_empty());
+        assert_eq!(
+            Violation::TorqueHigh {
+                value: 21500,
+                limit: 21000
+            }
+            .to_string(),
+            "扭矩 21.500 超过上限 21.0"
+        );
+        assert_eq!(
+            Violation::MonitorHigh {
+                monitored: Monitored::Angle,
+                value: 600,
+                limit: 500
+            }
+            .to_string(),
+            "监控角度 60.0 超过监控上限 50.0"
+        );
+    }
+}
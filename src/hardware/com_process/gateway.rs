@@ -0,0 +1,69 @@
+use std::{sync::mpsc, time::Instant};
+
+use tracing::{debug, error, info};
+
+use crate::{
+    app_data::RadioConfig,
+    hardware::message::usb::{USBLocalPacket, USBLocalPayload},
+    message::{GatewayInfo, ResponseAction},
+};
+
+#[derive(Debug, Clone)]
+pub struct GatewayContext {
+    pub port: String,
+    pub radio: Option<RadioConfig>,
+    pub rf_status: Option<GatewayInfo>,
+    pub last_report: Instant,
+}
+
+impl GatewayContext {
+    pub fn new(port: &str, radio: Option<RadioConfig>) -> Self {
+        Self {
+            port: port.to_string(),
+            radio,
+            rf_status: None,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn usb_update(&mut self, packet: &USBLocalPacket) {
+        debug!("收到网关 {} 的数据包 {:?}", self.port, packet);
+        match &packet.payload {
+            USBLocalPayload::RFStatus(rf_status) => {
+                self.rf_status = Some(GatewayInfo {
+                    port: self.port.clone(),
+                    rssi: rf_status.rssi,
+                    snr: rf_status.snr,
+                    rscp: rf_status.rscp,
+                });
+            }
+            USBLocalPayload::RFControl(rf_control) => {
+                info!("网关 {} 当前射频参数: {:?}", self.port, rf_control);
+                // 写入后的回读和重试由串口读写线程完成
+                if let Some(radio) = &self.radio {
+                    if !radio.matches(rf_control) {
+                        error!(
+                            "网关 {} 的射频参数与配置不一致, 配置为: {:?}",
+                            self.port, radio
+                        );
+                    }
+                }
+            }
+            USBLocalPayload::MACMode(mac_mode) => {
+                info!("网关 {} 当前 MAC 模式: {}", self.port, mac_mode.mode);
+            }
+        }
+    }
+
+    pub fn interval_update(&mut self, redis_sender: &mpsc::Sender<ResponseAction>) {
+        if self.last_report.elapsed() > std::time::Duration::from_secs(120) {
+            self.last_report = Instant::now();
+            // 只发布上次发布之后收到的链路状态
+            if let Some(rf_status) = self.rf_status.take() {
+                if let Err(e) = redis_sender.send(ResponseAction::GatewayStatus(rf_status)) {
+                    error!("网关 {} 无法发送状态: {:?}", self.port, e);
+                }
+            }
+        }
+    }
+}
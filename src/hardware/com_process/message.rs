@@ -49,23 +49,79 @@ pub fn query_energy(mac: u32, sender: &mpsc::Sender<WRCPacket>) -> anyhow::Resul
     Ok(())
 }
 
+pub fn query_generic(mac: u32, sender: &mpsc::Sender<WRCPacket>) -> anyhow::Result<()> {
+    let mut flag = WRCPacketFlag(0);
+    flag.set_direction(true);
+    flag.set_type(6);
+    let mut payload_flag = WRCPayloadGetInfoFlag(0);
+    payload_flag.set_generic(true);
+    let query_packet = WRCPacket {
+        sequence_id: 0,
+        mac,
+        flag,
+        payload_len: 1u8,
+        payload: WRCPayload::GetInfo(WRCPayloadGetInfo { flag: payload_flag }),
+    };
+
+    sender.send(query_packet)?;
+
+    Ok(())
+}
+
+pub fn query_timing(mac: u32, sender: &mpsc::Sender<WRCPacket>) -> anyhow::Result<()> {
+    let mut flag = WRCPacketFlag(0);
+    flag.set_direction(true);
+    flag.set_type(6);
+    let mut payload_flag = WRCPayloadGetInfoFlag(0);
+    payload_flag.set_timing(true);
+    let query_packet = WRCPacket {
+        sequence_id: 0,
+        mac,
+        flag,
+        payload_len: 1u8,
+        payload: WRCPayload::GetInfo(WRCPayloadGetInfo { flag: payload_flag }),
+    };
+
+    sender.send(query_packet)?;
+
+    Ok(())
+}
+
+pub fn query_network(mac: u32, sender: &mpsc::Sender<WRCPacket>) -> anyhow::Result<()> {
+    let mut flag = WRCPacketFlag(0);
+    flag.set_direction(true);
+    flag.set_type(6);
+    let mut payload_flag = WRCPayloadGetInfoFlag(0);
+    payload_flag.set_network(true);
+    let query_packet = WRCPacket {
+        sequence_id: 0,
+        mac,
+        flag,
+        payload_len: 1u8,
+        payload: WRCPayload::GetInfo(WRCPayloadGetInfo { flag: payload_flag }),
+    };
+
+    sender.send(query_packet)?;
+
+    Ok(())
+}
+
 fn verify_mac_serial(
     com: &mut ComProcess,
     wrc: &WRCPacket,
     tx: &mpsc::Sender<ResponseAction>,
-) -> bool {
-    if let Some(idx) = com.wrenches_mac_map.get(&wrc.mac) {
-        if let Some(wrench) = com.wrenches.get_mut(*idx) {
-            if let Some(serial_idx) = com.wrenches_serial_map.get(&wrench.serial) {
-                if serial_idx == idx {
-                    wrench.com_update(wrc, tx);
-                    return true;
-                }
-            }
-        }
+) -> anyhow::Result<bool> {
+    let mut registry = com
+        .registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+    if let Some(wrench) = registry.find_by_mac(&com.port, wrc.mac) {
+        wrench.com_update(wrc, &com.writer, tx);
+        return Ok(true);
     }
 
-    false
+    Ok(false)
 }
 
 pub fn process_com_message(
@@ -79,21 +135,35 @@ pub fn process_com_message(
         if serial == 0 {
             return Ok(());
         }
-        if let hash_map::Entry::Vacant(e) = com.wrenches_serial_map.entry(serial) {
-            info!("新扳手 {:X} 上线绑定到Mac: {:X}", serial, wrc.mac);
-            let idx = com.wrenches.len();
-            e.insert(idx);
-            com.wrenches_mac_map.insert(wrc.mac, idx);
-            com.wrenches.push(WrenchContext::new(wrc.mac, serial));
-            query_energy(wrc.mac, &com.writer)?;
-        } else {
-            info!("扳手 {:X} 迁移到Mac: {:X}", serial, wrc.mac);
-            let idx = *com.wrenches_serial_map.get(&serial).unwrap();
-            com.wrenches[idx].mac_reconnect(wrc.mac, &com.writer, tx);
-            com.wrenches_mac_map.insert(wrc.mac, idx);
-            query_energy(wrc.mac, &com.writer)?;
-        }
-    } else if !verify_mac_serial(com, wrc, tx) {
+
+        let mut registry = com
+            .registry
+            .lock()
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+        registry.mac_map.insert((com.port.clone(), wrc.mac), serial);
+        let wrench = match registry.wrenches.entry(serial) {
+            hash_map::Entry::Vacant(e) => {
+                info!("新扳手 {:X} 上线绑定到Mac: {:X}", serial, wrc.mac);
+                e.insert(WrenchContext::new(
+                    &com.port,
+                    wrc.mac,
+                    serial,
+                    com.config.wrench.clone(),
+                ))
+            }
+            hash_map::Entry::Occupied(e) => {
+                info!(
+                    "扳手 {:X} 迁移到网关: {}, Mac: {:X}",
+                    serial, com.port, wrc.mac
+                );
+                let wrench = e.into_mut();
+                wrench.mac_reconnect(&com.port, wrc.mac, &com.writer, tx);
+                wrench
+            }
+        };
+        wrench.sync_time(&com.writer);
+        query_energy(wrc.mac, &com.writer)?;
+    } else if !verify_mac_serial(com, wrc, tx)? {
         info!("不匹配的Mac: {:X}, 重新查询序列号", wrc.mac);
         query_serial(wrc.mac, &com.writer)?;
     }
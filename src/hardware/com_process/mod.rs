@@ -1,10 +1,16 @@
+mod energy;
+mod evaluate;
+mod gateway;
 mod message;
+pub mod network;
 mod port;
 mod redis;
-mod wrench;
+pub mod registry;
+mod request;
+pub mod store;
+pub mod wrench;
 
 use std::{
-    collections::HashMap,
     matches,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -14,36 +20,57 @@ use std::{
     thread::JoinHandle,
 };
 
-use bus::BusReader;
-
 use tracing::{debug, error, info, span, Level};
 
-use crate::message::{RequiredAction, ResponseAction, WrenchInfo};
+use crate::{
+    app_data::AppConfig,
+    message::{RequiredAction, ResponseAction},
+};
 
 use self::{
-    message::process_com_message,
-    port::read_write_loop,
-    redis::process_message_from_redis,
-    wrench::{WrenchContext, WrenchStatus},
+    gateway::GatewayContext, message::process_com_message, port::read_write_loop,
+    redis::process_message_from_redis, registry::SharedRegistry, wrench::WrenchStatus,
 };
 
-use super::message::wrc::WRCPacket;
+use super::message::{wrc::WRCPacket, ComPacket};
 
 pub struct ComProcess {
-    pub reader: Receiver<WRCPacket>,
+    pub port: String,
+    pub reader: Receiver<ComPacket>,
     pub writer: Sender<WRCPacket>,
-    pub handle: JoinHandle<()>,
-    pub wrenches_mac_map: HashMap<u32, usize>,
-    pub wrenches_serial_map: HashMap<u128, usize>,
-    pub wrenches: Vec<WrenchContext>,
-    pub connection_pending: Vec<WrenchInfo>,
+    pub handle: JoinHandle<anyhow::Result<()>>,
+    pub gateway: GatewayContext,
+    pub registry: SharedRegistry,
+    pub config: AppConfig,
 }
 
 fn com_update(com: &mut ComProcess, tx: &mpsc::Sender<ResponseAction>) -> anyhow::Result<()> {
-    for wrench in com.wrenches.iter_mut() {
+    com.gateway.interval_update(tx);
+
+    // 只更新当前由本网关到达的扳手, 每个扳手单独加锁, 避免长时间阻塞其它网关和 HTTP 线程
+    let serials = com
+        .registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?
+        .wrenches
+        .values()
+        .filter(|w| w.port == com.port)
+        .map(|w| w.serial)
+        .collect::<Vec<_>>();
+
+    for serial in serials {
+        let mut registry = com
+            .registry
+            .lock()
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+        let registry = &mut *registry;
+        let wrench = match registry.wrenches.get_mut(&serial) {
+            Some(w) if w.port == com.port => w,
+            _ => continue,
+        };
         wrench.interval_update(&com.writer, tx);
         if wrench.connect_id.is_empty() && !matches!(wrench.status, WrenchStatus::Disconnected) {
-            if let Some(mut wrench_info) = com.connection_pending.pop() {
+            if let Some(mut wrench_info) = registry.connection_pending.pop() {
                 wrench.connect_id = wrench_info.connect_id.clone();
                 wrench_info.wrench_serial = wrench.serial;
                 tx.send(ResponseAction::BindResponse(wrench_info))?;
@@ -58,8 +85,10 @@ pub fn com_process<'a>(
     exit_required: Arc<AtomicBool>,
     port: impl Into<std::borrow::Cow<'a, str>>,
     tx: mpsc::Sender<ResponseAction>,
-    mut rx: BusReader<RequiredAction>,
-) {
+    rx: Receiver<RequiredAction>,
+    registry: SharedRegistry,
+    config: AppConfig,
+) -> anyhow::Result<()> {
     let port = port.into();
     let mut com = {
         let (thread_writer, reader) = mpsc::channel();
@@ -67,32 +96,42 @@ pub fn com_process<'a>(
 
         let handle = {
             let port = port.to_string();
+            let radio = config.radio.clone();
             let exit_required = exit_required.clone();
             info!("启动串口读写线程");
             std::thread::spawn(move || {
                 span!(Level::ERROR, "串口读写线程", port = %port).in_scope(|| {
-                    read_write_loop(thread_reader, thread_writer, &port, exit_required);
-                });
+                    read_write_loop(thread_reader, thread_writer, &port, radio, exit_required)
+                })
             })
         };
         ComProcess {
+            port: port.to_string(),
             reader,
             writer,
             handle,
-            connection_pending: Vec::new(),
-            wrenches_mac_map: HashMap::new(),
-            wrenches_serial_map: HashMap::new(),
-            wrenches: Vec::new(),
+            gateway: GatewayContext::new(&port, config.radio.clone()),
+            registry,
+            config,
         }
     };
 
     info!("启动处理循环");
     while !exit_required.load(Ordering::Acquire) {
-        if let Ok(wrc) = com.reader.try_recv() {
-            // debug!("收到串口消息: {:02X?}", wrc);
-            if let Err(e) = process_com_message(&mut com, &wrc, &tx) {
-                error!("处理串口消息失败: {}", e);
+        if com.handle.is_finished() {
+            error!("串口读写线程意外退出, 结束处理循环");
+            break;
+        }
+
+        match com.reader.try_recv() {
+            Ok(ComPacket::WRC(wrc)) => {
+                // debug!("收到串口消息: {:02X?}", wrc);
+                if let Err(e) = process_com_message(&mut com, &wrc, &tx) {
+                    error!("处理串口消息失败: {}", e);
+                }
             }
+            Ok(ComPacket::USBLocal(usb)) => com.gateway.usb_update(&usb),
+            Err(_) => {}
         }
 
         if let Ok(action) = rx.try_recv() {
@@ -106,4 +145,14 @@ pub fn com_process<'a>(
             error!("定时更新失败: {}", e);
         }
     }
+
+    // 本线程退出后由其它网关重新发现这些扳手
+    if let Ok(mut registry) = com.registry.lock() {
+        registry.release_port(&com.port);
+    };
+
+    match com.handle.join() {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("串口读写线程异常退出")),
+    }
 }
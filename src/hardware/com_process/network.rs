@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Local};
+
+use crate::hardware::message::wrc::WRCPayloadInfoNetwork;
+
+#[derive(Debug, Clone)]
+pub struct NetworkSample {
+    pub time: DateTime<Local>,
+    pub collisions: u16,
+    pub crc_errors: u16,
+    pub tx_count: u16,
+    pub rx_wanted_count: u16,
+    pub rx_unwanted_count: u16,
+    pub rssi: i8,
+    pub snr: i8,
+    pub rscp: i8,
+}
+
+impl From<&WRCPayloadInfoNetwork> for NetworkSample {
+    fn from(info: &WRCPayloadInfoNetwork) -> Self {
+        Self {
+            time: Local::now(),
+            collisions: info.packets.collisions,
+            crc_errors: info.packets.crc_errors,
+            tx_count: info.packets.tx_count,
+            rx_wanted_count: info.packets.rx_wanted_count,
+            rx_unwanted_count: info.packets.rx_unwanted_count,
+            rssi: info.rf.rx_rssi,
+            snr: info.rf.rx_snr,
+            rscp: info.rf.rx_rscp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkWarning {
+    RssiDrop(i16),
+    CrcErrors(u16),
+}
+
+/// 扳手链路质量的历史记录, 用于发现信号变差的趋势
+#[derive(Debug, Clone, Default)]
+pub struct NetworkHistory {
+    pub samples: VecDeque<NetworkSample>,
+    rssi_warned: bool,
+    crc_warned: bool,
+}
+
+impl NetworkHistory {
+    /// 记录一次采样, 返回新出现的告警, 同一告警在恢复之前只报告一次
+    pub fn push(
+        &mut self,
+        sample: NetworkSample,
+        max_len: usize,
+        rssi_drop_threshold: i16,
+        crc_error_threshold: u16,
+    ) -> Vec<NetworkWarning> {
+        // 计数器在扳手重启后会清零
+        let crc_delta = match self.samples.back() {
+            Some(last) if sample.crc_errors >= last.crc_errors => {
+                sample.crc_errors - last.crc_errors
+            }
+            Some(_) => sample.crc_errors,
+            None => 0,
+        };
+
+        self.samples.push_back(sample);
+        while self.samples.len() > max_len.max(1) {
+            self.samples.pop_front();
+        }
+
+        let mut warnings = vec![];
+
+        let rssi_drop = self.rssi_drop();
+        if rssi_drop >= rssi_drop_threshold {
+            if !self.rssi_warned {
+                warnings.push(NetworkWarning::RssiDrop(rssi_drop));
+            }
+            self.rssi_warned = true;
+        } else {
+            self.rssi_warned = false;
+        }
+
+        if crc_delta >= crc_error_threshold {
+            if !self.crc_warned {
+                warnings.push(NetworkWarning::CrcErrors(crc_delta));
+            }
+            self.crc_warned = true;
+        } else {
+            self.crc_warned = false;
+        }
+
+        warnings
+    }
+
+    /// 较早一半采样与较新一半采样的平均 RSSI 之差
+    fn rssi_drop(&self) -> i16 {
+        let half = self.samples.len() / 2;
+        if half < 3 {
+            return 0;
+        }
+
+        // 历史记录较长时 i16 的累加会溢出, 平均值仍在 i8 的范围内
+        let average = |samples: &mut dyn Iterator<Item = &NetworkSample>| -> i16 {
+            let rssi = samples.map(|s| s.rssi as i32).collect::<Vec<_>>();
+            (rssi.iter().sum::<i32>() / rssi.len() as i32) as i16
+        };
+        let older = average(&mut self.samples.iter().take(half));
+        let newer = average(&mut self.samples.iter().skip(self.samples.len() - half));
+
+        older - newer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::{NetworkHistory, NetworkSample, NetworkWarning};
+
+    fn sample(rssi: i8, crc_errors: u16) -> NetworkSample {
+        NetworkSample {
+            time: Local::now(),
+            collisions: 0,
+            crc_errors,
+            tx_count: 0,
+            rx_wanted_count: 0,
+            rx_unwanted_count: 0,
+            rssi,
+            snr: 0,
+            rscp: 0,
+        }
+    }
+
+    #[test]
+    fn network_warning_test() {
+        let mut history = NetworkHistory::default();
+        for rssi in [-60, -60, -60, -75, -75] {
+            assert!(history.push(sample(rssi, 0), 6, 10, 5).is_empty());
+        }
+        assert_eq!(
+            history.push(sample(-75, 0), 6, 10, 5),
+            vec![NetworkWarning::RssiDrop(15)]
+        );
+        // 告警恢复之前不会重复报告
+        assert!(history.push(sample(-75, 0), 6, 10, 5).is_empty());
+
+        assert_eq!(
+            history.push(sample(-75, 8), 6, 20, 5),
+            vec![NetworkWarning::CrcErrors(8)]
+        );
+        // 计数器清零不会被当作错误增加
+        assert!(history.push(sample(-75, 2), 6, 20, 5).is_empty());
+
+        let mut history = NetworkHistory::default();
+        for _ in 0..1000 {
+            assert!(history.push(sample(-100, 0), 1000, 10, 5).is_empty());
+        }
+    }
+}
@@ -3,15 +3,24 @@ use std::{
         atomic::{AtomicBool, Ordering},
         mpsc, Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use anyhow::bail;
+
 use serialport::SerialPort;
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
-use crate::hardware::{
-    message::wrc::WRCPacket,
-    sm7bits::{self, SM7BitControlBits, SM_7_BIT_END_BYTE},
+use crate::{
+    app_data::RadioConfig,
+    hardware::{
+        message::{
+            usb::{USBLocalPacket, USBLocalPayload, USBLocalPayloadRFControl},
+            wrc::WRCPacket,
+            ComPacket,
+        },
+        sm7bits::{self, SM7BitControlBits, SM_7_BIT_END_BYTE},
+    },
 };
 
 fn open_port<'a>(
@@ -69,7 +78,7 @@ fn read_packet(
     readed
 }
 
-fn reader(exit_required: Arc<AtomicBool>, port: &mut Box<dyn SerialPort>) -> Option<WRCPacket> {
+fn reader(exit_required: Arc<AtomicBool>, port: &mut Box<dyn SerialPort>) -> Option<ComPacket> {
     let readed = read_packet(exit_required, port);
     if readed.is_empty() {
         return None;
@@ -77,28 +86,94 @@ fn reader(exit_required: Arc<AtomicBool>, port: &mut Box<dyn SerialPort>) -> Opt
 
     match sm7bits::decode(&readed) {
         Ok((SM7BitControlBits::WRC, decoded)) => match WRCPacket::try_from(decoded) {
-            Ok(p) => Some(p),
+            Ok(p) => Some(ComPacket::WRC(p)),
             Err(e) => {
                 error!("无法解析字节流内容: {readed:02X?}, 原因: {e}");
                 None
             }
         },
+        Ok((SM7BitControlBits::USBLocal, decoded)) => match USBLocalPacket::try_from(decoded) {
+            Ok(p) => Some(ComPacket::USBLocal(p)),
+            Err(e) => {
+                error!("无法解析网关字节流内容: {readed:02X?}, 原因: {e}");
+                None
+            }
+        },
         Err(e) => {
             error!("无法按照sm7bits协议转换字节流: {readed:02X?}, 原因: {e}");
             None
         }
-        _ => None,
     }
 }
 
+fn write_radio(port: &mut Box<dyn SerialPort>, radio: &RadioConfig) -> anyhow::Result<()> {
+    let packet = USBLocalPacket {
+        packet_type: 2,
+        payload_len: 10,
+        payload: USBLocalPayload::RFControl(USBLocalPayloadRFControl {
+            freq_hz: radio.freq_hz,
+            rsvd: 0,
+            txpower: radio.txpower,
+            lora: radio.lora.clone(),
+        }),
+    };
+    let data: Vec<u8> = packet.try_into().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let encoded = sm7bits::encode(&data, SM7BitControlBits::USBLocal);
+    port.write_all(&encoded)?;
+
+    Ok(())
+}
+
+/// 只有类型没有负载的 RFControl 帧请求网关返回当前的射频参数
+fn query_radio(port: &mut Box<dyn SerialPort>) -> anyhow::Result<()> {
+    let encoded = sm7bits::encode(&[2, 0], SM7BitControlBits::USBLocal);
+    port.write_all(&encoded)?;
+
+    Ok(())
+}
+
+/// 等待网关回读射频参数的时间
+const RADIO_VERIFY_TIMEOUT: Duration = Duration::from_secs(3);
+/// 回读不一致或没有应答时最多写入的次数
+const RADIO_MAX_WRITES: u8 = 3;
+
+/// 写入射频参数并请求回读, 返回写入时间和已写入的次数; 写入失败时关闭串口, 重新打开后再次写入
+///
+/// 回读不一致或超时的次数超过上限时返回错误, 该网关将被停用
+fn program_radio(
+    opened_port: &mut Option<Box<dyn SerialPort>>,
+    radio: &RadioConfig,
+    writes: u8,
+) -> anyhow::Result<Option<(Instant, u8)>> {
+    if writes >= RADIO_MAX_WRITES {
+        bail!("写入 {} 次后射频参数仍未生效", writes);
+    }
+    let p = match opened_port.as_mut() {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+
+    info!("向网关写入射频参数: {:?}", radio);
+    if let Err(e) = write_radio(p, radio).and_then(|_| query_radio(p)) {
+        error!("无法写入射频参数至网关: {}", e);
+        *opened_port = None;
+        return Ok(None);
+    }
+
+    Ok(Some((Instant::now(), writes + 1)))
+}
+
 pub fn read_write_loop<'a>(
     rx: mpsc::Receiver<WRCPacket>,
-    tx: mpsc::Sender<WRCPacket>,
+    tx: mpsc::Sender<ComPacket>,
     port: impl Into<std::borrow::Cow<'a, str>>,
+    radio: Option<RadioConfig>,
     exit_required: Arc<AtomicBool>,
-) {
+) -> anyhow::Result<()> {
     let port = port.into();
     let mut opened_port = None;
+    // 等待回读的射频参数, 记录最近一次写入的时间和已写入的次数
+    let mut radio_check: Option<(Instant, u8)> = None;
 
     debug!("启动读写循环");
     while !exit_required.load(Ordering::Acquire) {
@@ -107,15 +182,46 @@ pub fn read_write_loop<'a>(
             if opened_port.is_some() {
                 debug!("端口成功打开");
             }
+            if let Some(radio) = radio.as_ref() {
+                radio_check = program_radio(&mut opened_port, radio, 0)?;
+            }
             continue;
         }
 
         if let Some(readed) = reader(exit_required.clone(), opened_port.as_mut().unwrap()) {
+            if let (
+                ComPacket::USBLocal(USBLocalPacket {
+                    payload: USBLocalPayload::RFControl(rf_control),
+                    ..
+                }),
+                Some(radio),
+                Some((_, writes)),
+            ) = (&readed, radio.as_ref(), radio_check)
+            {
+                if radio.matches(rf_control) {
+                    info!("网关射频参数回读一致");
+                    radio_check = None;
+                } else {
+                    error!("网关回读的射频参数 {:?} 与配置不一致, 重新写入", rf_control);
+                    radio_check = program_radio(&mut opened_port, radio, writes)?;
+                }
+            }
             if let Err(e) = tx.send(readed) {
                 error!("无法发送数据包至串口处理线程: {}", e);
             }
         }
 
+        if let (Some(radio), Some((sent_at, writes))) = (radio.as_ref(), radio_check) {
+            if sent_at.elapsed() > RADIO_VERIFY_TIMEOUT {
+                error!("网关没有回读射频参数, 重新写入");
+                radio_check = program_radio(&mut opened_port, radio, writes)?;
+            }
+        }
+        // 写入射频参数失败时串口已被关闭
+        if opened_port.is_none() {
+            continue;
+        }
+
         if let Ok(packet) = rx.try_recv() {
             debug!("发送数据包: {packet:X?}");
             match TryInto::<Vec<u8>>::try_into(packet) {
@@ -132,4 +238,6 @@ pub fn read_write_loop<'a>(
             }
         }
     }
+
+    Ok(())
 }
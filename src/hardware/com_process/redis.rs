@@ -1,6 +1,8 @@
 use std::{collections::HashMap, sync::mpsc};
 
-use crate::message::{ConnectInfo, RequiredAction, ResponseAction, TaskInfo};
+use crate::message::{
+    BeepInfo, ConnectInfo, ReleaseInfo, ReorderInfo, RequiredAction, ResponseAction,
+};
 
 use super::{wrench::WrenchStatus, ComProcess};
 
@@ -9,15 +11,22 @@ fn check_connect(
     mut target: ConnectInfo,
     tx: &mpsc::Sender<ResponseAction>,
 ) -> anyhow::Result<()> {
+    let registry = com
+        .registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+    if !registry.is_owned_by(target.wrench_serial, &com.port) {
+        return Ok(());
+    }
+
     target.status = false;
 
-    if let Some(index) = com.wrenches_serial_map.get(&target.wrench_serial) {
-        if let Some(wrench) = com.wrenches.get(*index) {
-            if !matches!(wrench.status, WrenchStatus::Disconnected) {
-                target.status = true;
-                tx.send(ResponseAction::ConnectStatus(target))?;
-                return Ok(());
-            }
+    if let Some(wrench) = registry.wrenches.get(&target.wrench_serial) {
+        if !matches!(wrench.status, WrenchStatus::Disconnected) {
+            target.status = true;
+            tx.send(ResponseAction::ConnectStatus(target))?;
+            return Ok(());
         }
     }
 
@@ -31,55 +40,77 @@ pub fn process_message_from_redis(
     tx: &mpsc::Sender<ResponseAction>,
 ) -> anyhow::Result<()> {
     match action {
-        RequiredAction::BindWrench(target) => {
-            com.connection_pending.push(target);
-        }
+        // 绑定请求由分发线程统一放入等待队列
+        RequiredAction::BindWrench(_) => {}
         RequiredAction::CheckConnect(target) => check_connect(com, target, tx)?,
         RequiredAction::SendTask((msg_id, target)) => {
+            let mut registry = com
+                .registry
+                .lock()
+                .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
             let mut need_update = HashMap::new();
             for t in target {
                 let serial = u128::from_str_radix(&t.wrench_serial, 16).unwrap_or(0);
-                if let Some(index) = com.wrenches_serial_map.get(&serial) {
-                    if com.wrenches.get_mut(*index).is_some() {
-                        need_update.entry(serial).or_insert(vec![]).push(t);
-                        continue;
-                    }
+                if registry.is_owned_by(serial, &com.port) {
+                    need_update.entry(serial).or_insert(vec![]).push(t);
                 }
-
-                let task_info = TaskInfo {
-                    msg_id,
-                    wrench_serial: serial,
-                    status: false,
-                };
-
-                tx.send(ResponseAction::TaskStatus(task_info))?;
-                return Ok(());
             }
 
             for (serial, tasks) in need_update {
-                if let Some(index) = com.wrenches_serial_map.get(&serial) {
-                    if let Some(wrench) = com.wrenches.get_mut(*index) {
-                        wrench.redis_update(
-                            RequiredAction::SendTask((msg_id.clone(), tasks)),
-                            &com.writer,
-                            tx,
-                        );
-                    }
-                }
-            }
-        }
-        RequiredAction::TaskCancel((wrench_serial, task_id)) => {
-            let serial = u128::from_str_radix(&wrench_serial, 16).unwrap_or(0);
-            if let Some(index) = com.wrenches_serial_map.get(&serial) {
-                if let Some(wrench) = com.wrenches.get_mut(*index) {
+                if let Some(wrench) = registry.wrenches.get_mut(&serial) {
                     wrench.redis_update(
-                        RequiredAction::TaskCancel((wrench_serial, task_id)),
+                        RequiredAction::SendTask((msg_id.clone(), tasks)),
                         &com.writer,
                         tx,
                     );
                 }
             }
         }
+        RequiredAction::TaskCancel(ref cancel_info) => {
+            let mut registry = com
+                .registry
+                .lock()
+                .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+            let serials = match cancel_info.wrench_serial {
+                Some(serial) => vec![serial],
+                None => registry.wrenches.keys().copied().collect(),
+            };
+            for serial in serials {
+                if !registry.is_owned_by(serial, &com.port) {
+                    continue;
+                }
+                if let Some(wrench) = registry.wrenches.get_mut(&serial) {
+                    wrench.redis_update(action.clone(), &com.writer, tx);
+                }
+            }
+        }
+        RequiredAction::Beep(BeepInfo {
+            wrench_serial: serial,
+            ..
+        })
+        | RequiredAction::TaskReorder(ReorderInfo {
+            wrench_serial: serial,
+            ..
+        })
+        | RequiredAction::TaskRelease(ReleaseInfo {
+            wrench_serial: serial,
+            ..
+        })
+        | RequiredAction::ClearJoints(serial) => {
+            let mut registry = com
+                .registry
+                .lock()
+                .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+            if !registry.is_owned_by(serial, &com.port) {
+                return Ok(());
+            }
+            if let Some(wrench) = registry.wrenches.get_mut(&serial) {
+                wrench.redis_update(action, &com.writer, tx);
+            }
+        }
     }
 
     Ok(())
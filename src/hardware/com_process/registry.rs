@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use tracing::{debug, error};
+
+use crate::message::{
+    BeepInfo, CancelInfo, ConnectInfo, ReleaseInfo, ReorderInfo, RequiredAction, ResponseAction,
+    TaskInfo, WrenchInfo,
+};
+
+use super::wrench::WrenchContext;
+
+/// 所有串口处理线程共享的扳手登记表, 以序列号为键
+#[derive(Debug, Default)]
+pub struct WrenchRegistry {
+    pub wrenches: HashMap<u128, WrenchContext>,
+    pub mac_map: HashMap<(String, u32), u128>,
+    pub connection_pending: Vec<WrenchInfo>,
+    /// 每个网关处理线程接收消息的通道, 以串口名为键
+    pub workers: HashMap<String, mpsc::Sender<RequiredAction>>,
+}
+
+pub type SharedRegistry = Arc<Mutex<WrenchRegistry>>;
+
+impl WrenchRegistry {
+    pub fn is_owned_by(&self, serial: u128, port: &str) -> bool {
+        self.wrenches
+            .get(&serial)
+            .map(|w| w.port.as_str() == port)
+            .unwrap_or(false)
+    }
+
+    pub fn is_reachable(&self, serial: u128) -> bool {
+        self.wrenches
+            .get(&serial)
+            .map(|w| !w.port.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// 当前可以到达该扳手的网关
+    fn owner(&self, serial: u128) -> Option<String> {
+        self.wrenches
+            .get(&serial)
+            .filter(|w| !w.port.is_empty())
+            .map(|w| w.port.clone())
+    }
+
+    /// 需要处理该消息的网关, 工位范围的取消需要所有网关处理
+    fn target_ports(&self, action: &RequiredAction) -> Vec<String> {
+        let mut ports = match action {
+            RequiredAction::BindWrench(_) => vec![],
+            RequiredAction::CheckConnect(target) => {
+                self.owner(target.wrench_serial).into_iter().collect()
+            }
+            RequiredAction::SendTask((_, tasks)) => tasks
+                .iter()
+                .filter_map(|t| self.owner(parse_serial(&t.wrench_serial)))
+                .collect(),
+            RequiredAction::TaskCancel(target) => match target.wrench_serial {
+                Some(serial) => self.owner(serial).into_iter().collect(),
+                None => self.workers.keys().cloned().collect(),
+            },
+            RequiredAction::TaskRelease(target) => {
+                self.owner(target.wrench_serial).into_iter().collect()
+            }
+            RequiredAction::TaskReorder(target) => {
+                self.owner(target.wrench_serial).into_iter().collect()
+            }
+            RequiredAction::Beep(target) => self.owner(target.wrench_serial).into_iter().collect(),
+            RequiredAction::ClearJoints(serial) => self.owner(*serial).into_iter().collect(),
+        };
+        ports.sort();
+        ports.dedup();
+        ports
+    }
+
+    /// 工位上是否有可以到达的扳手还有未完成的任务
+    pub fn has_station_task(&self, station_ip: &str) -> bool {
+        self.wrenches.values().any(|w| {
+            !w.port.is_empty()
+                && w.current_task
+                    .iter()
+                    .chain(w.pending_task.iter())
+                    .any(|t| t.station_ip == station_ip)
+        })
+    }
+
+    /// 查找当前通过该网关和 Mac 地址可以到达的扳手
+    pub fn find_by_mac(&mut self, port: &str, mac: u32) -> Option<&mut WrenchContext> {
+        let serial = self.mac_map.get(&(port.to_string(), mac))?;
+        self.wrenches
+            .get_mut(serial)
+            .filter(|w| w.port.as_str() == port && w.mac == mac)
+    }
+
+    /// 网关处理线程退出后, 其下的扳手等待被其它网关重新发现
+    pub fn release_port(&mut self, port: &str) {
+        self.workers.remove(port);
+        self.mac_map.retain(|(p, _), _| p.as_str() != port);
+        for wrench in self.wrenches.values_mut() {
+            if wrench.port.as_str() == port {
+                wrench.port.clear();
+            }
+        }
+    }
+}
+
+fn parse_serial(serial: &str) -> u128 {
+    u128::from_str_radix(serial, 16).unwrap_or(0)
+}
+
+/// 将来自 Redis 或 HTTP 的消息分发到到达目标扳手的串口处理线程, 对于当前没有网关可以到达的扳手直接应答失败
+pub fn dispatch(
+    registry: &SharedRegistry,
+    action: RequiredAction,
+    tx: &mpsc::Sender<ResponseAction>,
+) -> anyhow::Result<()> {
+    let mut registry_lock = registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+    match &action {
+        RequiredAction::CheckConnect(target) => {
+            if !registry_lock.is_reachable(target.wrench_serial) {
+                debug!("扳手 {:X} 当前没有网关可以到达", target.wrench_serial);
+                tx.send(ResponseAction::ConnectStatus(ConnectInfo {
+                    status: false,
+                    ..target.clone()
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::SendTask((msg_id, tasks)) => {
+            if let Some(unknown) = tasks
+                .iter()
+                .map(|t| parse_serial(&t.wrench_serial))
+                .find(|s| !registry_lock.is_reachable(*s))
+            {
+                debug!("扳手 {:X} 当前没有网关可以到达", unknown);
+                tx.send(ResponseAction::TaskStatus(TaskInfo {
+                    msg_id: msg_id.clone(),
+                    wrench_serial: unknown,
+                    status: false,
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::TaskCancel(target) => {
+            let desc = match (target.wrench_serial, &target.station_ip) {
+                (Some(serial), _) if !registry_lock.is_reachable(serial) => {
+                    Some("扳手未绑定或当前没有网关可以到达")
+                }
+                (None, Some(station_ip)) if !registry_lock.has_station_task(station_ip) => {
+                    Some("工位上没有需要取消的任务")
+                }
+                _ => None,
+            };
+            if let Some(desc) = desc {
+                debug!("无法取消任务: {}", desc);
+                tx.send(ResponseAction::CancelStatus(CancelInfo {
+                    status: false,
+                    desc: desc.to_string(),
+                    ..target.clone()
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::TaskRelease(target) => {
+            if !registry_lock.is_reachable(target.wrench_serial) {
+                debug!("扳手 {:X} 当前没有网关可以到达", target.wrench_serial);
+                tx.send(ResponseAction::ReleaseStatus(ReleaseInfo {
+                    status: false,
+                    desc: "扳手未绑定或当前没有网关可以到达".to_string(),
+                    ..target.clone()
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::TaskReorder(target) => {
+            if !registry_lock.is_reachable(target.wrench_serial) {
+                debug!("扳手 {:X} 当前没有网关可以到达", target.wrench_serial);
+                tx.send(ResponseAction::ReorderStatus(ReorderInfo {
+                    status: false,
+                    desc: "扳手未绑定或当前没有网关可以到达".to_string(),
+                    ..target.clone()
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::Beep(target) => {
+            if !registry_lock.is_reachable(target.wrench_serial) {
+                debug!("扳手 {:X} 当前没有网关可以到达", target.wrench_serial);
+                tx.send(ResponseAction::BeepStatus(BeepInfo {
+                    status: false,
+                    ..target.clone()
+                }))?;
+                return Ok(());
+            }
+        }
+        RequiredAction::ClearJoints(serial) => {
+            if !registry_lock.is_reachable(*serial) {
+                debug!("扳手 {:X} 当前没有网关可以到达", serial);
+                return Ok(());
+            }
+        }
+        RequiredAction::BindWrench(target) => {
+            registry_lock.connection_pending.push(target.clone());
+            return Ok(());
+        }
+    }
+
+    for port in registry_lock.target_ports(&action) {
+        match registry_lock.workers.get(&port) {
+            Some(worker) => {
+                debug!("将消息 {:?} 发送到串口 {} 的处理线程", action, port);
+                if let Err(e) = worker.send(action.clone()) {
+                    error!("无法发送消息到串口 {} 的处理线程: {}", port, e);
+                }
+            }
+            None => debug!("串口 {} 没有处理线程", port),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, time::Duration, time::Instant};
+
+use crate::hardware::message::wrc::{WRCPacket, WRCPacketFlag, WRCPayload};
+
+/// 需要扳手通过 StatusReport 确认的请求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestKind {
+    SetJoint(u16),
+    ClearJointData,
+    SetWrenchTime,
+    /// 请求的起始 joint 序号
+    GetJointData(u16),
+    /// 携带需要应答的消息 id
+    Beep(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct OutstandingRequest {
+    pub kind: RequestKind,
+    pub packet: WRCPacket,
+    pub sent_at: Instant,
+    pub retransmits: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestTable {
+    last_seq_id: u16,
+    outstanding: HashMap<u16, OutstandingRequest>,
+}
+
+impl RequestTable {
+    /// 序列号 0 保留给不需要应答的查询
+    fn next_sequence_id(&mut self) -> u16 {
+        self.last_seq_id = self.last_seq_id.wrapping_add(1);
+        if self.last_seq_id == 0 {
+            self.last_seq_id = 1;
+        }
+        self.last_seq_id
+    }
+
+    pub fn packet(
+        &mut self,
+        mac: u32,
+        packet_type: u8,
+        payload_len: u8,
+        payload: WRCPayload,
+    ) -> WRCPacket {
+        let mut flag = WRCPacketFlag(0);
+        flag.set_direction(true);
+        flag.set_type(packet_type);
+
+        WRCPacket {
+            sequence_id: self.next_sequence_id(),
+            mac,
+            flag,
+            payload_len,
+            payload,
+        }
+    }
+
+    pub fn track(&mut self, kind: RequestKind, packet: &WRCPacket) {
+        // 同类请求只保留最新的一个
+        self.outstanding.retain(|_, r| r.kind != kind);
+        self.outstanding.insert(
+            packet.sequence_id,
+            OutstandingRequest {
+                kind,
+                packet: packet.clone(),
+                sent_at: Instant::now(),
+                retransmits: 0,
+            },
+        );
+    }
+
+    pub fn confirm(&mut self, seq_id: u16) -> Option<OutstandingRequest> {
+        self.outstanding.remove(&seq_id)
+    }
+
+    pub fn is_pending(&self, kind: &RequestKind) -> bool {
+        self.outstanding.values().any(|r| &r.kind == kind)
+    }
+
+    pub fn is_pending_by(&self, f: impl Fn(&RequestKind) -> bool) -> bool {
+        self.outstanding.values().any(|r| f(&r.kind))
+    }
+
+    /// 不再等待满足条件的请求的确认
+    pub fn cancel_by(&mut self, f: impl Fn(&RequestKind) -> bool) {
+        self.outstanding.retain(|_, r| !f(&r.kind));
+    }
+
+    /// 返回需要重发的数据包, 以及重发次数耗尽后被放弃的请求
+    pub fn expire(
+        &mut self,
+        timeout: Duration,
+        max_retransmits: u8,
+    ) -> (Vec<WRCPacket>, Vec<OutstandingRequest>) {
+        let mut resend = vec![];
+        let mut abandoned = vec![];
+
+        self.outstanding.retain(|_, r| {
+            if r.sent_at.elapsed() < timeout {
+                return true;
+            }
+            if r.retransmits >= max_retransmits {
+                abandoned.push(r.clone());
+                return false;
+            }
+            r.retransmits += 1;
+            r.sent_at = Instant::now();
+            resend.push(r.packet.clone());
+            true
+        });
+
+        (resend, abandoned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RequestKind, RequestTable};
+    use crate::hardware::message::wrc::WRCPayload;
+
+    #[test]
+    fn sequence_id_test() {
+        let mut table = RequestTable {
+            last_seq_id: u16::MAX - 1,
+            ..Default::default()
+        };
+        assert_eq!(table.next_sequence_id(), u16::MAX);
+        assert_eq!(table.next_sequence_id(), 1);
+    }
+
+    #[test]
+    fn retransmit_test() {
+        let mut table = RequestTable::default();
+        let packet = table.packet(0x1234, 10, 0, WRCPayload::ClearJointData);
+        table.track(RequestKind::ClearJointData, &packet);
+        assert!(table.is_pending(&RequestKind::ClearJointData));
+
+        let (resend, abandoned) = table.expire(Duration::ZERO, 1);
+        assert_eq!(resend.len(), 1);
+        assert_eq!(resend[0].sequence_id, packet.sequence_id);
+        assert!(abandoned.is_empty());
+
+        let (resend, abandoned) = table.expire(Duration::ZERO, 1);
+        assert!(resend.is_empty());
+        assert_eq!(abandoned.len(), 1);
+        assert!(!table.is_pending(&RequestKind::ClearJointData));
+
+        let packet = table.packet(0x1234, 10, 0, WRCPayload::ClearJointData);
+        table.track(RequestKind::ClearJointData, &packet);
+        assert!(table.confirm(packet.sequence_id).is_some());
+        assert!(table.confirm(packet.sequence_id).is_none());
+    }
+}
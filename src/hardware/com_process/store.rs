@@ -0,0 +1,440 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{app_data::WrenchConfig, hardware::message::wrc::WRCPayloadInlineJointDataFlag};
+
+use super::{
+    registry::SharedRegistry,
+    wrench::{JointData, JointTask, WrenchContext, WrenchStatus, WrenchTask},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JointSnapshot {
+    pub joint_id: i32,
+    pub unix_time: u32,
+    pub flag: u8,
+    pub torque: i32,
+    pub angle: i16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskSnapshot {
+    pub wrench_task_id: u16,
+    pub redis_task_id: String,
+    pub redis_task_detail_id: String,
+    pub msg_id: String,
+    pub last_report: i64,
+    pub delivered: bool,
+    pub joints_task: JointTask,
+    pub joints_recv: Vec<JointSnapshot>,
+    #[serde(default)]
+    pub bolt_nok: u16,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub station_ip: String,
+    #[serde(default)]
+    pub priority: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WrenchSnapshot {
+    pub serial: String,
+    pub connect_id: String,
+    pub total_joints: u32,
+    pub current_task: Option<TaskSnapshot>,
+    pub pending_task: Vec<TaskSnapshot>,
+    pub finished_task: Vec<TaskSnapshot>,
+}
+
+impl From<&JointData> for JointSnapshot {
+    fn from(joint: &JointData) -> Self {
+        Self {
+            joint_id: joint.joint_id,
+            unix_time: joint.unix_time,
+            flag: joint.flag.0,
+            torque: joint.torque,
+            angle: joint.angle,
+        }
+    }
+}
+
+impl From<JointSnapshot> for JointData {
+    fn from(joint: JointSnapshot) -> Self {
+        Self {
+            joint_id: joint.joint_id,
+            unix_time: joint.unix_time,
+            flag: WRCPayloadInlineJointDataFlag(joint.flag),
+            torque: joint.torque,
+            angle: joint.angle,
+        }
+    }
+}
+
+impl From<&WrenchTask> for TaskSnapshot {
+    fn from(task: &WrenchTask) -> Self {
+        Self {
+            wrench_task_id: task.wrench_task_id,
+            redis_task_id: task.redis_task_id.clone(),
+            redis_task_detail_id: task.redis_task_detail_id.clone(),
+            msg_id: task.msg_id.clone(),
+            last_report: task.last_report.timestamp(),
+            delivered: task.delivered,
+            joints_task: task.joints_task.clone(),
+            joints_recv: task.joints_recv.iter().map(JointSnapshot::from).collect(),
+            bolt_nok: task.bolt_nok,
+            locked: task.locked,
+            station_ip: task.station_ip.clone(),
+            priority: task.priority,
+        }
+    }
+}
+
+impl From<TaskSnapshot> for WrenchTask {
+    fn from(task: TaskSnapshot) -> Self {
+        Self {
+            wrench_task_id: task.wrench_task_id,
+            redis_task_id: task.redis_task_id,
+            redis_task_detail_id: task.redis_task_detail_id,
+            msg_id: task.msg_id,
+            last_report: Local
+                .timestamp_opt(task.last_report, 0)
+                .single()
+                .unwrap_or_else(Local::now),
+            delivered: task.delivered,
+            joints_task: task.joints_task,
+            joints_recv: task.joints_recv.into_iter().map(JointData::from).collect(),
+            bolt_nok: task.bolt_nok,
+            locked: task.locked,
+            station_ip: task.station_ip,
+            priority: task.priority,
+            send_failed: false,
+        }
+    }
+}
+
+impl From<&WrenchContext> for WrenchSnapshot {
+    fn from(wrench: &WrenchContext) -> Self {
+        Self {
+            serial: format!("{:X}", wrench.serial),
+            connect_id: wrench.connect_id.clone(),
+            total_joints: wrench.total_joints,
+            current_task: wrench.current_task.as_ref().map(TaskSnapshot::from),
+            pending_task: wrench.pending_task.iter().map(TaskSnapshot::from).collect(),
+            finished_task: wrench
+                .finished_task
+                .iter()
+                .map(TaskSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+impl WrenchSnapshot {
+    /// 恢复的扳手在被网关重新发现之前都处于断开状态
+    pub fn into_context(self, config: WrenchConfig) -> anyhow::Result<WrenchContext> {
+        let serial = u128::from_str_radix(&self.serial, 16)?;
+        let mut wrench = WrenchContext::new("", 0, serial, config);
+        wrench.status = WrenchStatus::Disconnected;
+        wrench.connect_id = self.connect_id;
+        wrench.total_joints = self.total_joints;
+        wrench.current_task = self.current_task.map(WrenchTask::from);
+        wrench.pending_task = self
+            .pending_task
+            .into_iter()
+            .map(WrenchTask::from)
+            .collect();
+        let skip = self
+            .finished_task
+            .len()
+            .saturating_sub(wrench.config.finished_task_limit.max(1));
+        wrench.finished_task = self
+            .finished_task
+            .into_iter()
+            .skip(skip)
+            .map(WrenchTask::from)
+            .collect();
+
+        Ok(wrench)
+    }
+}
+
+/// 只对会写入状态文件的字段求摘要, 拧紧数据只会追加, 用数量代替内容
+fn task_digest(task: &WrenchTask, hasher: &mut DefaultHasher) {
+    task.wrench_task_id.hash(hasher);
+    task.last_report.timestamp().hash(hasher);
+    task.delivered.hash(hasher);
+    task.joints_recv.len().hash(hasher);
+    task.bolt_nok.hash(hasher);
+    task.locked.hash(hasher);
+    task.priority.hash(hasher);
+}
+
+fn wrench_digest(wrench: &WrenchContext, hasher: &mut DefaultHasher) {
+    wrench.serial.hash(hasher);
+    wrench.connect_id.hash(hasher);
+    wrench.total_joints.hash(hasher);
+    wrench.current_task.is_some().hash(hasher);
+    wrench.pending_task.len().hash(hasher);
+    wrench.finished_task.len().hash(hasher);
+    wrench
+        .current_task
+        .iter()
+        .chain(wrench.pending_task.iter())
+        .chain(wrench.finished_task.iter())
+        .for_each(|task| task_digest(task, hasher));
+}
+
+pub struct StateStore {
+    path: PathBuf,
+    last_digest: Option<u64>,
+    last_check: Instant,
+}
+
+impl StateStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            last_digest: None,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// 从磁盘恢复扳手状态到登记表, 文件不存在时视为首次启动
+    /// 文件损坏时移到一旁并以空状态启动, 不影响网关运行
+    pub fn load(&mut self, registry: &SharedRegistry, config: &WrenchConfig) -> anyhow::Result<()> {
+        if !self.path.exists() {
+            info!("状态文件 {} 不存在, 跳过恢复", self.path.display());
+            return Ok(());
+        }
+
+        let wrenches = match self.read(config) {
+            Ok(wrenches) => wrenches,
+            Err(e) => {
+                let backup = self
+                    .path
+                    .with_extension(format!("corrupt-{}", Local::now().format("%Y%m%d%H%M%S")));
+                error!(
+                    "无法读取状态文件 {}: {:?}, 移动到 {} 后以空状态启动",
+                    self.path.display(),
+                    e,
+                    backup.display()
+                );
+                std::fs::rename(&self.path, &backup)?;
+                return Ok(());
+            }
+        };
+
+        let mut registry = registry
+            .lock()
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+        for wrench in wrenches {
+            info!(
+                "从状态文件恢复扳手 {:X}, 待执行任务数: {}",
+                wrench.serial,
+                wrench.pending_task.len() + wrench.current_task.iter().len()
+            );
+            registry.wrenches.insert(wrench.serial, wrench);
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, config: &WrenchConfig) -> anyhow::Result<Vec<WrenchContext>> {
+        let content = std::fs::read(&self.path)?;
+        let snapshots: Vec<WrenchSnapshot> = serde_json::from_slice(&content)?;
+        snapshots
+            .into_iter()
+            .map(|snapshot| snapshot.into_context(config.clone()))
+            .collect()
+    }
+
+    /// 每秒检查一次登记表, 内容变化时写入磁盘
+    pub fn update(&mut self, registry: &SharedRegistry) -> anyhow::Result<()> {
+        if self.last_check.elapsed() < Duration::from_secs(1) {
+            return Ok(());
+        }
+        self.last_check = Instant::now();
+        self.save(registry)
+    }
+
+    pub fn save(&mut self, registry: &SharedRegistry) -> anyhow::Result<()> {
+        let (digest, snapshots) = {
+            let registry = registry
+                .lock()
+                .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+            let mut wrenches = registry.wrenches.values().collect::<Vec<_>>();
+            wrenches.sort_by_key(|wrench| wrench.serial);
+
+            let mut hasher = DefaultHasher::new();
+            wrenches
+                .iter()
+                .for_each(|wrench| wrench_digest(wrench, &mut hasher));
+            let digest = hasher.finish();
+            if self.last_digest == Some(digest) {
+                return Ok(());
+            }
+
+            let snapshots = wrenches
+                .into_iter()
+                .map(WrenchSnapshot::from)
+                .collect::<Vec<_>>();
+            (digest, snapshots)
+        };
+
+        // 先写入临时文件再替换, 避免写入中断导致状态文件损坏
+        let content = serde_json::to_vec(&snapshots)?;
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, &content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        debug!("扳手状态已写入 {}", self.path.display());
+        self.last_digest = Some(digest);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::StateStore;
+    use crate::{
+        app_data::WrenchConfig,
+        hardware::{
+            com_process::{
+                registry::SharedRegistry,
+                wrench::{JointData, JointTask, WrenchContext, WrenchStatus, WrenchTask},
+            },
+            message::wrc::WRCPayloadInlineJointDataFlag,
+        },
+    };
+
+    fn task(wrench_task_id: u16, redis_task_id: &str) -> WrenchTask {
+        WrenchTask {
+            wrench_task_id,
+            redis_task_id: redis_task_id.to_string(),
+            redis_task_detail_id: format!("{}-1", redis_task_id),
+            msg_id: "msg".to_string(),
+            last_report: Local::now(),
+            delivered: true,
+            joints_task: JointTask {
+                torque: 20000,
+                torque_angle_start: 0,
+                torque_upper_tol: 1000,
+                torque_lower_tol: 1000,
+                angle: 0,
+                angle_upper_tol: 0,
+                angle_lower_tol: 0,
+                task_repeat_times: 2,
+                bolt_num: 3,
+                control_mode: 0,
+                work_mode: 0,
+                unit: 0,
+                monitor_min: Some(100),
+                monitor_max: None,
+                max_nok: 3,
+                nok_action: Default::default(),
+            },
+            joints_recv: vec![],
+            bolt_nok: 0,
+            locked: false,
+            station_ip: "10.0.0.1".to_string(),
+            priority: 0,
+            send_failed: false,
+        }
+    }
+
+    #[test]
+    fn save_load_test() {
+        let path = std::env::temp_dir().join(format!("store_test_{}.json", std::process::id()));
+        let config = WrenchConfig::default();
+
+        let mut wrench = WrenchContext::new("COM1", 0x1234, 0xABCD, config.clone());
+        wrench.connect_id = "connect".to_string();
+        wrench.total_joints = 70000;
+        let mut current = task(2, "current");
+        current.joints_recv.push(JointData {
+            joint_id: 69999,
+            unix_time: 1000,
+            flag: WRCPayloadInlineJointDataFlag(1),
+            torque: 20100,
+            angle: 300,
+        });
+        current.bolt_nok = 1;
+        current.locked = true;
+        wrench.current_task = Some(current);
+        let mut pending = task(3, "pending");
+        pending.priority = 5;
+        wrench.pending_task.push_back(pending);
+        wrench.finished_task.push(task(1, "finished"));
+
+        let registry = SharedRegistry::default();
+        registry
+            .lock()
+            .unwrap()
+            .wrenches
+            .insert(wrench.serial, wrench);
+        StateStore::new(&path).save(&registry).unwrap();
+
+        let restored = SharedRegistry::default();
+        StateStore::new(&path).load(&restored, &config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let restored = restored.lock().unwrap();
+        let wrench = restored.wrenches.get(&0xABCD).unwrap();
+        assert!(matches!(wrench.status, WrenchStatus::Disconnected));
+        assert_eq!(wrench.connect_id, "connect");
+        assert_eq!(wrench.total_joints, 70000);
+
+        let current = wrench.current_task.as_ref().unwrap();
+        assert_eq!(current.wrench_task_id, 2);
+        assert_eq!(current.redis_task_detail_id, "current-1");
+        assert_eq!(current.joints_recv.len(), 1);
+        assert_eq!(current.joints_recv[0].joint_id, 69999);
+        assert_eq!(current.joints_recv[0].flag.0, 1);
+        assert_eq!(current.joints_recv[0].torque, 20100);
+        assert_eq!(current.bolt_nok, 1);
+        assert!(current.locked);
+        assert_eq!(current.joints_task.monitor_min, Some(100));
+        assert_eq!(current.joints_task.max_nok, 3);
+
+        assert_eq!(wrench.pending_task.len(), 1);
+        assert_eq!(wrench.pending_task[0].redis_task_id, "pending");
+        assert_eq!(wrench.pending_task[0].priority, 5);
+        assert_eq!(wrench.finished_task.len(), 1);
+        assert_eq!(wrench.finished_task[0].redis_task_id, "finished");
+    }
+
+    #[test]
+    fn load_corrupt_test() {
+        let dir = std::env::temp_dir().join(format!("store_corrupt_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        std::fs::write(&path, b"[{\"serial\":").unwrap();
+
+        let registry = SharedRegistry::default();
+        StateStore::new(&path)
+            .load(&registry, &WrenchConfig::default())
+            .unwrap();
+        assert!(registry.lock().unwrap().wrenches.is_empty());
+        assert!(!path.exists());
+
+        let moved = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(moved.len(), 1);
+        assert!(moved[0].starts_with("state.corrupt-"));
+    }
+}
@@ -1,26 +1,40 @@
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::VecDeque,
     matches,
     sync::mpsc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::bail;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
 use crate::{
+    app_data::{ReworkAction, WrenchConfig},
     hardware::message::wrc::{
-        WRCPacket, WRCPacketFlag, WRCPayload, WRCPayloadGetJointData, WRCPayloadInlineJointData,
+        WRCPacket, WRCPayload, WRCPayloadGetJointData, WRCPayloadInfoEnergy, WRCPayloadInfoGeneric,
+        WRCPayloadInfoNetwork, WRCPayloadInfoTiming, WRCPayloadInlineJointData,
         WRCPayloadInlineJointDataFlag, WRCPayloadSetJoint, WRCPayloadSetJointFlag,
+        WRCPayloadSetWrenchTime, WRCPayloadStatusReport, WRCStatus,
+    },
+    message::{
+        AlarmInfo, BasicInfo, BeepInfo, CancelInfo, CancelTarget, ConnectInfo, FinishedInfo,
+        LifecycleInfo, NetworkInfo, NokLimitInfo, ReorderInfo, RequiredAction, ResponseAction,
+        TaskInfo, TaskStage,
     },
-    message::{BasicInfo, ConnectInfo, FinishedInfo, RequiredAction, ResponseAction, TaskInfo},
     redis::message::TaskRequestMsg,
 };
 
-use super::message::query_energy;
+use super::{
+    energy::{BatteryLevel, EnergyEvent, EnergyState},
+    evaluate::evaluate,
+    message::{query_energy, query_generic, query_network, query_timing},
+    network::{NetworkHistory, NetworkSample, NetworkWarning},
+    request::{RequestKind, RequestTable},
+};
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JointTask {
     pub torque: i32,
     pub torque_angle_start: i32,
@@ -34,6 +48,16 @@ pub struct JointTask {
     pub control_mode: u8,
     pub work_mode: u8,
     pub unit: u8,
+    /// 单一模式中监控量的下限和上限, 精度与监控量相同, 没有配置的一侧不限制
+    #[serde(default)]
+    pub monitor_min: Option<i32>,
+    #[serde(default)]
+    pub monitor_max: Option<i32>,
+    /// 每个螺栓完成之前累计不合格的最大次数, 为 0 时不限制
+    #[serde(default)]
+    pub max_nok: u16,
+    #[serde(default)]
+    pub nok_action: ReworkAction,
 }
 
 #[derive(Debug, Clone)]
@@ -52,8 +76,18 @@ pub struct WrenchTask {
     pub redis_task_detail_id: String,
     pub msg_id: String,
     pub last_report: DateTime<Local>,
+    pub delivered: bool,
     pub joints_task: JointTask,
     pub joints_recv: Vec<JointData>,
+    /// 当前螺栓累计不合格的次数, 螺栓完成所有重复次数后清零
+    pub bolt_nok: u16,
+    /// 不合格次数达到上限后锁定, 之后的结果不再计入
+    pub locked: bool,
+    pub station_ip: String,
+    /// 数值越大越优先, 等待队列按优先级排列
+    pub priority: u8,
+    /// 扳手在重发次数耗尽后仍未确认任务, 重新连接之前不再下发
+    pub send_failed: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -65,45 +99,445 @@ pub enum WrenchStatus {
 
 #[derive(Debug, Clone)]
 pub struct WrenchContext {
+    pub port: String,
     pub mac: u32,
     pub serial: u128,
     pub connect_id: String,
     pub voltage: Option<u16>,
+    pub energy: EnergyState,
     pub online_time: u64,
     pub last_recv: Instant,
     pub last_send: Instant,
-    pub last_send_id: u16,
+    /// 空闲时查询拧紧数据的间隔, 没有新数据时逐步退避
+    pub joint_poll_interval: Duration,
+    /// 扳手通过 InfoGeneric 报告的已存储 joint 数量
+    pub available_joints: Option<u16>,
+    /// 重连或抢占前正在收取扳手存储的数据, 完成之前不重新下发任务
+    pub draining: Option<Instant>,
+    /// 等待扳手确认清空后再应答的取消请求
+    pub pending_cancels: Vec<CancelInfo>,
+    pub last_task_send: Instant,
     pub last_report: Instant,
-    pub total_joints: u16,
+    pub last_clock_check: Instant,
+    pub last_network_check: Instant,
+    pub network: NetworkHistory,
+    /// 已收取的 joint 数量, joint 序号为 u16, 收到序号 65535 后数量会超出 u16 的范围
+    pub total_joints: u32,
     pub status: WrenchStatus,
     pub current_task: Option<WrenchTask>,
     pub pending_task: VecDeque<WrenchTask>,
     pub finished_task: Vec<WrenchTask>,
+    pub requests: RequestTable,
+    pub config: WrenchConfig,
 }
 
 impl WrenchContext {
-    pub fn new(mac: u32, serial: u128) -> Self {
+    pub fn new(port: &str, mac: u32, serial: u128, config: WrenchConfig) -> Self {
         let now = Instant::now();
 
         Self {
+            port: port.to_string(),
             mac,
             serial,
             connect_id: "".to_string(),
             voltage: None,
+            energy: EnergyState::default(),
             online_time: 0,
             last_recv: now,
             last_send: now,
-            last_send_id: 0,
+            joint_poll_interval: Duration::from_millis(config.joint_poll_idle_ms),
+            available_joints: None,
+            draining: None,
+            pending_cancels: Vec::new(),
+            last_task_send: now,
             last_report: now,
+            last_clock_check: now,
+            last_network_check: now,
+            network: NetworkHistory::default(),
             total_joints: 0,
             status: WrenchStatus::Connected,
             current_task: None,
             pending_task: VecDeque::new(),
             finished_task: Vec::new(),
+            requests: RequestTable::default(),
+            config,
+        }
+    }
+
+    fn send_packet(&self, packet: WRCPacket, com_sender: &mpsc::Sender<WRCPacket>) {
+        if let Err(e) = com_sender.send(packet) {
+            error!("扳手 {:X} 无法发送数据 {:?}", self.serial, e);
+        }
+    }
+
+    /// 发送需要扳手确认的请求, 超时未确认时将按照配置进行重发
+    fn send_request(
+        &mut self,
+        kind: RequestKind,
+        packet_type: u8,
+        payload_len: u8,
+        payload: WRCPayload,
+        com_sender: &mpsc::Sender<WRCPacket>,
+    ) {
+        let packet = self
+            .requests
+            .packet(self.mac, packet_type, payload_len, payload);
+        self.requests.track(kind, &packet);
+        self.send_packet(packet, com_sender);
+    }
+
+    fn process_status_report(
+        &mut self,
+        report: &WRCPayloadStatusReport,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let request = match self.requests.confirm(report.target_seqid) {
+            Some(r) => r,
+            None => {
+                debug!(
+                    "扳手 {:X} 的状态报告没有对应的请求: {:?}",
+                    self.serial, report
+                );
+                return;
+            }
+        };
+
+        let status = WRCStatus::try_from(report.status);
+        if let (RequestKind::GetJointData(start), Ok(WRCStatus::GetJointRangeError)) =
+            (&request.kind, &status)
+        {
+            // 请求的范围超出了扳手存储的数据, 重新查询扳手的 joint 数量
+            info!(
+                "扳手 {:X} 没有从 {} 开始的 joint 数据, 重新查询数量",
+                self.serial, start
+            );
+            self.available_joints = None;
+            query_generic(self.mac, com_sender).ok();
+            return;
+        }
+
+        let succeeded = matches!(
+            status,
+            Ok(WRCStatus::Success) | Ok(WRCStatus::JointsDeleted) | Ok(WRCStatus::GetJointSuccess)
+        );
+        if let RequestKind::Beep(msg_id) = &request.kind {
+            self.beep_response(msg_id, succeeded, redis_sender);
+        }
+        if request.kind == RequestKind::ClearJointData {
+            self.cancel_response(succeeded, redis_sender);
+        }
+        if !succeeded {
+            error!(
+                "扳手 {:X} 拒绝了请求 {:?}, 状态为: {}",
+                self.serial, request.kind, report.status
+            );
+            if let RequestKind::SetJoint(task_id) = request.kind {
+                self.send_task_failed(task_id, redis_sender);
+            }
+            return;
+        }
+
+        debug!("扳手 {:X} 确认了请求 {:?}", self.serial, request.kind);
+        if let RequestKind::SetJoint(task_id) = request.kind {
+            if let Some(current) = self.current_task.as_mut() {
+                if current.wrench_task_id == task_id {
+                    current.delivered = true;
+                    info!("扳手 {:X} 已确认接收任务 {}", self.serial, task_id);
+                    self.current_task_event(TaskStage::Confirmed, redis_sender);
+                }
+            }
+        }
+    }
+
+    fn beep_response(
+        &self,
+        msg_id: &str,
+        status: bool,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        if let Err(e) = redis_sender.send(ResponseAction::BeepStatus(BeepInfo {
+            msg_id: msg_id.to_string(),
+            wrench_serial: self.serial,
+            status,
+        })) {
+            error!("扳手 {:X} 发送蜂鸣结果失败: {:?}", self.serial, e);
+        }
+    }
+
+    /// 已结束的任务只保留最近的一部分, 用于匹配断线期间上传的数据
+    fn finish_task(&mut self, task: WrenchTask) {
+        self.finished_task.push(task);
+        let limit = self.config.finished_task_limit.max(1);
+        if self.finished_task.len() > limit {
+            let excess = self.finished_task.len() - limit;
+            self.finished_task.drain(..excess);
+        }
+    }
+
+    /// 扳手拒绝或一直没有确认任务时只向上游报告一次, 不再重复下发
+    fn send_task_failed(&mut self, task_id: u16, redis_sender: &mpsc::Sender<ResponseAction>) {
+        match self.current_task.as_mut() {
+            Some(current) if current.wrench_task_id == task_id && !current.send_failed => {
+                current.send_failed = true;
+            }
+            _ => return,
+        }
+        error!("扳手 {:X} 未能接收任务 {}", self.serial, task_id);
+        self.current_task_event(TaskStage::SendFailed, redis_sender);
+    }
+
+    /// 应答所有等待扳手清空确认的取消请求
+    fn cancel_response(&mut self, status: bool, redis_sender: &mpsc::Sender<ResponseAction>) {
+        for mut cancel_info in std::mem::take(&mut self.pending_cancels) {
+            cancel_info.status = status;
+            cancel_info.desc = if status {
+                "任务已取消"
+            } else {
+                "扳手未确认清空任务"
+            }
+            .to_string();
+            if let Err(e) = redis_sender.send(ResponseAction::CancelStatus(cancel_info)) {
+                error!("扳手 {:X} 发送取消结果失败: {:?}", self.serial, e);
+            }
+        }
+    }
+
+    /// 向上游报告任务在队列中的变化
+    fn task_event(
+        &self,
+        task: &WrenchTask,
+        stage: TaskStage,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        debug!(
+            "扳手 {:X} 的任务 {} 进入阶段 {:?}",
+            self.serial, task.redis_task_id, stage
+        );
+        let passed_count = task.passed_count();
+        if let Err(e) = redis_sender.send(ResponseAction::TaskLifecycle(LifecycleInfo {
+            msg_id: task.msg_id.clone(),
+            wrench_serial: self.serial,
+            task_id: task.redis_task_id.clone(),
+            task_detail_id: task.redis_task_detail_id.clone(),
+            stage,
+            finished_bolts: passed_count / task.repeat_times(),
+            bolt_num: task.joints_task.bolt_num as usize,
+            passed_count,
+            joint_count: task.joints_recv.len(),
+            pending_count: self.pending_task.len(),
+        })) {
+            error!("扳手 {:X} 无法发送任务状态: {:?}", self.serial, e);
+        }
+    }
+
+    fn current_task_event(&self, stage: TaskStage, redis_sender: &mpsc::Sender<ResponseAction>) {
+        if let Some(task) = &self.current_task {
+            self.task_event(task, stage, redis_sender);
+        }
+    }
+
+    /// 使用主机时间校准扳手时钟
+    pub fn sync_time(&mut self, com_sender: &mpsc::Sender<WRCPacket>) {
+        let unix_time = chrono::Utc::now().timestamp() as u32;
+        debug!("向扳手 {:X} 同步时间: {}", self.serial, unix_time);
+        self.send_request(
+            RequestKind::SetWrenchTime,
+            8,
+            4u8,
+            WRCPayload::SetWrenchTime(WRCPayloadSetWrenchTime { unix_time }),
+            com_sender,
+        );
+    }
+
+    fn process_info_timing(
+        &mut self,
+        info_timing: &WRCPayloadInfoTiming,
+        com_sender: &mpsc::Sender<WRCPacket>,
+    ) {
+        let drift = info_timing.wrench_time as i64 - chrono::Utc::now().timestamp();
+        debug!("扳手 {:X} 的时钟偏差为 {} 秒", self.serial, drift);
+        if drift.unsigned_abs() > self.config.clock_drift_threshold_secs as u64 {
+            info!(
+                "扳手 {:X} 的时钟偏差 {} 秒超过阈值, 重新同步时间",
+                self.serial, drift
+            );
+            self.sync_time(com_sender);
+        }
+    }
+
+    fn send_alarm(
+        &self,
+        title: &str,
+        code: &str,
+        level: u8,
+        desc: String,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        if let Err(e) = redis_sender.send(ResponseAction::Alarm(AlarmInfo {
+            wrench_serial: self.serial,
+            title: title.to_string(),
+            code: code.to_string(),
+            level,
+            desc,
+        })) {
+            error!("扳手 {:X} 无法发送告警: {:?}", self.serial, e);
+        }
+    }
+
+    /// 按扳手型号的放电曲线估算电量百分比和剩余可拧紧次数
+    pub fn battery_estimate(&self) -> Option<(u8, u32)> {
+        let voltage = self.voltage?;
+        let curve = self.config.battery_curve(self.serial)?;
+        let percent = curve.percent(voltage)?;
+
+        Some((percent, curve.remaining_joints(percent)))
+    }
+
+    fn process_info_energy(
+        &mut self,
+        info_energy: &WRCPayloadInfoEnergy,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let voltage = info_energy.battery_voltage_mv;
+        self.voltage = Some(voltage);
+
+        let events = self.energy.update(
+            info_energy,
+            self.config.low_voltage_mv,
+            self.config.critical_voltage_mv,
+        );
+        for event in events {
+            let (code, level, desc) = match event {
+                EnergyEvent::Charging(true) => ("BATTERY_CHARGING", 0, "扳手开始充电".to_string()),
+                EnergyEvent::Charging(false) => {
+                    ("BATTERY_DISCHARGING", 0, "扳手停止充电".to_string())
+                }
+                EnergyEvent::Hibernated(true) => {
+                    ("WRENCH_HIBERNATED", 0, "扳手进入休眠".to_string())
+                }
+                EnergyEvent::Hibernated(false) => ("WRENCH_AWAKE", 0, "扳手退出休眠".to_string()),
+                EnergyEvent::PowerConnected(true) => {
+                    ("POWER_CONNECTED", 0, "扳手接入电源".to_string())
+                }
+                EnergyEvent::PowerConnected(false) => {
+                    ("POWER_DISCONNECTED", 0, "扳手断开电源".to_string())
+                }
+                EnergyEvent::BatteryLevel(BatteryLevel::Low, mv) => {
+                    ("BATTERY_LOW", 1, format!("扳手电量低, 当前电压 {} mV", mv))
+                }
+                EnergyEvent::BatteryLevel(BatteryLevel::Critical, mv) => (
+                    "BATTERY_CRITICAL",
+                    2,
+                    format!("扳手电量严重不足, 当前电压 {} mV", mv),
+                ),
+                EnergyEvent::BatteryLevel(BatteryLevel::Normal, mv) => (
+                    "BATTERY_NORMAL",
+                    0,
+                    format!("扳手电量恢复, 当前电压 {} mV", mv),
+                ),
+            };
+            info!("扳手 {:X} 能量状态变化: {}", self.serial, desc);
+            self.send_alarm("电源状态", code, level, desc, redis_sender);
+        }
+    }
+
+    fn process_info_network(
+        &mut self,
+        info_network: &WRCPayloadInfoNetwork,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let sample = NetworkSample::from(info_network);
+        debug!("扳手 {:X} 的链路状态: {:?}", self.serial, sample);
+
+        if let Err(e) = redis_sender.send(ResponseAction::NetworkStatus(NetworkInfo {
+            wrench_serial: self.serial,
+            collisions: sample.collisions,
+            crc_errors: sample.crc_errors,
+            tx_count: sample.tx_count,
+            rx_wanted_count: sample.rx_wanted_count,
+            rx_unwanted_count: sample.rx_unwanted_count,
+            rssi: sample.rssi,
+            snr: sample.snr,
+            rscp: sample.rscp,
+        })) {
+            error!("扳手 {:X} 无法发送链路状态: {:?}", self.serial, e);
+        }
+
+        let warnings = self.network.push(
+            sample,
+            self.config.network_history_len,
+            self.config.rssi_drop_threshold_db,
+            self.config.crc_error_threshold,
+        );
+        for warning in warnings {
+            let (code, desc) = match warning {
+                NetworkWarning::RssiDrop(drop) => (
+                    "NETWORK_RSSI_DROP",
+                    format!("扳手信号强度持续下降 {} dB", drop),
+                ),
+                NetworkWarning::CrcErrors(count) => (
+                    "NETWORK_CRC_ERRORS",
+                    format!("扳手 CRC 错误增加了 {} 次", count),
+                ),
+            };
+            info!("扳手 {:X} 链路质量告警: {}", self.serial, desc);
+            self.send_alarm("链路质量告警", code, 1, desc, redis_sender);
+        }
+    }
+
+    fn retransmit_update(
+        &mut self,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let timeout = Duration::from_millis(self.config.request_timeout_ms);
+        let (resend, abandoned) = self.requests.expire(timeout, self.config.max_retransmits);
+
+        for mut packet in resend {
+            debug!(
+                "扳手 {:X} 未确认序列号为 {} 的请求, 进行重发",
+                self.serial, packet.sequence_id
+            );
+            packet.mac = self.mac;
+            self.send_packet(packet, com_sender);
+        }
+        for request in abandoned {
+            error!(
+                "扳手 {:X} 在 {} 次重发后仍未确认请求 {:?}",
+                self.serial, request.retransmits, request.kind
+            );
+            if let RequestKind::Beep(msg_id) = &request.kind {
+                self.beep_response(msg_id, false, redis_sender);
+            }
+            if request.kind == RequestKind::ClearJointData {
+                self.cancel_response(false, redis_sender);
+            }
+            if let RequestKind::SetJoint(task_id) = request.kind {
+                self.send_task_failed(task_id, redis_sender);
+            }
+        }
+
+        // 任务未被确认时重新下发, 避免扳手处于空闲状态
+        if let (WrenchStatus::Working, Some(current)) = (&self.status, &self.current_task) {
+            let kind = RequestKind::SetJoint(current.wrench_task_id);
+            if self.draining.is_none()
+                && !current.delivered
+                && !current.send_failed
+                && !self.requests.is_pending(&kind)
+                && self.last_task_send.elapsed() > timeout
+            {
+                self.send_task(com_sender, redis_sender);
+            }
         }
     }
 
-    pub fn com_update(&mut self, packet: &WRCPacket, redis_sender: &mpsc::Sender<ResponseAction>) {
+    pub fn com_update(
+        &mut self,
+        packet: &WRCPacket,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
         self.online_time = self
             .online_time
             .saturating_add(self.last_recv.elapsed().as_secs());
@@ -112,9 +546,10 @@ impl WrenchContext {
         // debug!("扳手 {:X} 收到数据包 {:X?}", self.serial, packet);
         match &packet.payload {
             WRCPayload::InfoEnergy(info_energy) => {
-                self.voltage = Some(info_energy.battery_voltage_mv);
+                self.process_info_energy(info_energy, redis_sender);
             }
-            WRCPayload::InlineJointData(inline_joint_data) => {
+            WRCPayload::InlineJointData(inline_joint_data)
+            | WRCPayload::JointData(inline_joint_data) => {
                 debug!(
                     "收到扳手 {:X} 的joint 数据 {:?}",
                     self.serial, inline_joint_data
@@ -122,115 +557,390 @@ impl WrenchContext {
                 if let Err(e) = self.process_inline_joint_data(inline_joint_data, redis_sender) {
                     error!("处理来自扳手的 joint 数据失败: {:?}", e);
                 }
+                // 收到数据即视为请求完成, 继续请求剩余的数据
+                self.requests
+                    .cancel_by(|k| matches!(k, RequestKind::GetJointData(_)));
+                self.request_joints(com_sender);
+                // 收取完已知的数据后再次核对数量, 确认断线期间的数据已经全部收取
+                if self.draining.is_some()
+                    && !self
+                        .requests
+                        .is_pending_by(|k| matches!(k, RequestKind::GetJointData(_)))
+                {
+                    query_generic(self.mac, com_sender).ok();
+                }
+            }
+            WRCPayload::StatusReport(status_report) => {
+                self.process_status_report(status_report, com_sender, redis_sender);
+            }
+            WRCPayload::InfoTiming(info_timing) => {
+                self.process_info_timing(info_timing, com_sender);
+            }
+            WRCPayload::InfoNetwork(info_network) => {
+                self.process_info_network(info_network, redis_sender);
+            }
+            WRCPayload::InfoGeneric(info_generic) => {
+                self.process_info_generic(info_generic, com_sender, redis_sender);
             }
             _ => {}
         }
     }
 
+    fn process_info_generic(
+        &mut self,
+        info_generic: &WRCPayloadInfoGeneric,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        debug!(
+            "扳手 {:X} 存储了 {} 个 joint, 已收取 {} 个, 最后收到的序列号为 {}",
+            self.serial,
+            info_generic.joint_count,
+            self.total_joints,
+            info_generic.last_server_packet_seqid
+        );
+        self.available_joints = Some(info_generic.joint_count);
+
+        // 清空数据的请求尚未确认时, 扳手报告的数量还是清空之前的
+        if (info_generic.joint_count as u32) < self.total_joints
+            && !self.requests.is_pending(&RequestKind::ClearJointData)
+        {
+            let desc = format!(
+                "扳手存储了 {} 个 joint, 主机已记录 {} 个, 以扳手为准重新收取",
+                info_generic.joint_count, self.total_joints
+            );
+            info!("扳手 {:X} 的 joint 数量不一致: {}", self.serial, desc);
+            self.send_alarm(
+                "拧紧数据校对",
+                "JOINT_COUNT_MISMATCH",
+                0,
+                desc,
+                redis_sender,
+            );
+            self.total_joints = info_generic.joint_count as u32;
+        }
+
+        if info_generic.joint_count as u32 > self.total_joints {
+            self.joint_poll_interval = Duration::from_millis(self.config.joint_poll_idle_ms);
+            self.request_joints(com_sender);
+        } else {
+            // 没有新数据时加倍空闲查询间隔
+            self.joint_poll_interval = (self.joint_poll_interval * 2)
+                .min(Duration::from_millis(self.config.joint_poll_max_ms));
+            self.finish_drain(com_sender, redis_sender);
+        }
+    }
+
+    /// 断线期间的数据收取完成后, 清空扳手数据并按剩余数量重新下发任务
+    fn finish_drain(
+        &mut self,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let started = match self.draining.take() {
+            Some(t) => t,
+            None => return,
+        };
+        info!(
+            "扳手 {:X} 用时 {} 秒收取了扳手存储的数据, 重新下发任务",
+            self.serial,
+            started.elapsed().as_secs()
+        );
+
+        self.clear_task(com_sender);
+        if self.preempt_current(redis_sender) {
+            return;
+        }
+        // 断线期间已经完成的任务由 interval_update 切换到下一个任务
+        if let Some(current) = &self.current_task {
+            if current.passed_count() < current.required_count() {
+                self.send_task(com_sender, redis_sender);
+            }
+        }
+    }
+
+    /// 等待队列最前面的任务优先级更高时, 先收取当前任务的数据再切换, 避免清空扳手时丢失结果
+    fn check_preempt(&mut self, com_sender: &mpsc::Sender<WRCPacket>) {
+        if self.draining.is_some() || !matches!(self.status, WrenchStatus::Working) {
+            return;
+        }
+        if let (Some(current), Some(next)) = (&self.current_task, self.pending_task.front()) {
+            if next.priority > current.priority {
+                info!(
+                    "扳手 {:X} 收到更高优先级的任务 {}, 收取当前任务 {} 的数据后切换",
+                    self.serial, next.redis_task_id, current.redis_task_id
+                );
+                self.draining = Some(Instant::now());
+                query_generic(self.mac, com_sender).ok();
+            }
+        }
+    }
+
+    /// 将当前任务连同已收取的结果放回等待队列, 恢复执行时只需要完成剩余的数量
+    fn preempt_current(&mut self, redis_sender: &mpsc::Sender<ResponseAction>) -> bool {
+        let preempt = match (&self.current_task, self.pending_task.front()) {
+            (Some(current), Some(next)) => {
+                next.priority > current.priority
+                    && current.passed_count() < current.required_count()
+            }
+            _ => false,
+        };
+        if !preempt {
+            return false;
+        }
+
+        let mut task = self.current_task.take().unwrap();
+        task.delivered = false;
+        info!(
+            "扳手 {:X} 的任务 {} 被抢占, 已收取 {} 个结果",
+            self.serial,
+            task.redis_task_id,
+            task.joints_recv.len()
+        );
+        self.status = WrenchStatus::Connected;
+        let index = self.insert_task(task, true);
+        self.task_event(
+            &self.pending_task[index],
+            TaskStage::Preempted,
+            redis_sender,
+        );
+        true
+    }
+
+    /// 按优先级插入等待队列, 同优先级的任务保持先后顺序, ahead 为 true 时排在同优先级任务之前
+    fn insert_task(&mut self, task: WrenchTask, ahead: bool) -> usize {
+        let index = self
+            .pending_task
+            .iter()
+            .position(|x| {
+                if ahead {
+                    x.priority <= task.priority
+                } else {
+                    x.priority < task.priority
+                }
+            })
+            .unwrap_or(self.pending_task.len());
+        self.pending_task.insert(index, task);
+        index
+    }
+
+    /// 将指定的任务按给出的顺序移动到等待队列最前面, 并提升到队列中的最高优先级
+    fn reorder_task(
+        &mut self,
+        mut reorder_info: ReorderInfo,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let unknown = reorder_info
+            .task_ids
+            .iter()
+            .find(|id| !self.pending_task.iter().any(|x| &x.redis_task_id == *id));
+        if let Some(task_id) = unknown {
+            reorder_info.desc = format!("等待队列中没有任务 {}", task_id);
+        } else {
+            let top = self
+                .pending_task
+                .iter()
+                .map(|x| x.priority)
+                .max()
+                .unwrap_or(0);
+            let mut tasks = std::mem::take(&mut self.pending_task);
+            for task_id in reorder_info.task_ids.iter() {
+                while let Some(index) = tasks.iter().position(|x| &x.redis_task_id == task_id) {
+                    if let Some(mut task) = tasks.remove(index) {
+                        task.priority = top;
+                        self.pending_task.push_back(task);
+                    }
+                }
+            }
+            self.pending_task.extend(tasks);
+            reorder_info.status = true;
+            reorder_info.desc = "队列已调整".to_string();
+            info!("扳手 {:X} 的等待队列已调整", self.serial);
+            // 提升后的任务可能高于当前任务
+            self.check_preempt(com_sender);
+        }
+
+        reorder_info.wrench_serial = self.serial;
+        reorder_info.task_ids = self
+            .pending_task
+            .iter()
+            .map(|x| x.redis_task_id.clone())
+            .collect();
+        if let Err(e) = redis_sender.send(ResponseAction::ReorderStatus(reorder_info)) {
+            error!("扳手 {:X} 发送队列调整结果失败: {:?}", self.serial, e);
+        }
+    }
+
+    /// 按照扳手报告的数量批量请求尚未收取的 joint 数据
+    fn request_joints(&mut self, com_sender: &mpsc::Sender<WRCPacket>) {
+        if self
+            .requests
+            .is_pending_by(|k| matches!(k, RequestKind::GetJointData(_)))
+        {
+            return;
+        }
+        // 扳手报告的数量大于已收取的数量时, 起始序号一定在 u16 的范围内
+        let (start, remaining) = match self.available_joints {
+            Some(available) if available as u32 > self.total_joints => {
+                let start = self.total_joints as u16;
+                (start, available - start)
+            }
+            _ => return,
+        };
+
+        let joint_count = remaining.min(self.config.joint_batch_size.max(1) as u16) as u8;
+        debug!(
+            "向 {:X} 扳手请求从 {} 开始的 {} 个 joint, 该扳手的状态为 {:?}",
+            self.serial, start, joint_count, self.status
+        );
+        self.send_request(
+            RequestKind::GetJointData(start),
+            9,
+            3u8,
+            WRCPayload::GetJointData(WRCPayloadGetJointData {
+                joint_id_start: start,
+                joint_count,
+            }),
+            com_sender,
+        );
+    }
+
     fn process_inline_joint_data(
         &mut self,
         inline_joint_data: &[WRCPayloadInlineJointData],
         tx: &mpsc::Sender<ResponseAction>,
     ) -> Result<(), anyhow::Error> {
-        if matches!(self.status, WrenchStatus::Working) {
-            let wrench_task = self.current_task.as_mut().unwrap();
+        let mut inline_joint_data = inline_joint_data.to_vec();
+        inline_joint_data.sort_by_key(|x| x.joint_id);
+        let mut aborted = None;
+
+        for recv in inline_joint_data.into_iter() {
+            // joint 序号即数据在扳手中的存储位置, 小于已收取数量的是重复数据
+            if (recv.joint_id as u32) < self.total_joints {
+                debug!("重复的joint_id: {}", recv.joint_id);
+                continue;
+            }
+            self.total_joints = recv.joint_id as u32 + 1;
 
-            let mut inline_joint_data = inline_joint_data.to_vec();
-            inline_joint_data.sort_by_key(|x| x.joint_id);
-            let mut joints_set = wrench_task
+            // 按 task_id 找到所属任务, 断线期间可能已经完成了当前任务
+            let wrench_task = match self
+                .current_task
+                .as_mut()
+                .filter(|t| t.wrench_task_id == recv.task_id)
+                .or_else(|| {
+                    self.finished_task
+                        .iter_mut()
+                        .rev()
+                        .find(|t| t.wrench_task_id == recv.task_id)
+                }) {
+                Some(t) => t,
+                None => {
+                    debug!("不属于任务的task_id: {}", recv.task_id);
+                    continue;
+                }
+            };
+
+            if wrench_task
                 .joints_recv
                 .iter()
-                .map(|x| x.joint_id)
-                .collect::<HashSet<_>>();
-
-            let scale_down = |mut int: i32, mut scale: i32| -> String {
-                let mut frac = 0;
-                let mut level = 0;
-
-                while scale > 0 {
-                    frac += i32::pow(10, level) * (int % 10);
-                    int /= 10;
-                    scale -= 1;
-                    level += 1;
-                }
+                .any(|x| x.joint_id == recv.joint_id as i32)
+            {
+                debug!("重复的joint_id: {}", recv.joint_id);
+                continue;
+            }
 
-                format!("{}.{}", int, frac.abs())
+            let tmp = JointData {
+                joint_id: recv.joint_id as i32,
+                unix_time: recv.unix_time,
+                flag: recv.flag.clone(),
+                torque: recv.torque,
+                angle: recv.angle,
             };
 
-            for recv in inline_joint_data.into_iter() {
-                if recv.task_id != wrench_task.wrench_task_id {
-                    debug!("不是属于该任务的task_id: {}", recv.task_id);
-
-                    let mut joint_has_recvd = false;
-                    for finished in self.finished_task.iter() {
-                        if finished
-                            .joints_recv
-                            .iter()
-                            .any(|x| x.joint_id == recv.joint_id as i32)
-                        {
-                            debug!("重复的joint_id: {}", recv.joint_id);
-                            joint_has_recvd = true;
-                            break;
-                        }
-                    }
-
-                    if !joint_has_recvd {
-                        debug!("不属于任务的task_id: {}", recv.task_id);
-                        self.total_joints += 1;
-                    }
-                    continue;
-                }
+            let violations = evaluate(&wrench_task.joints_task, recv.torque, recv.angle);
+            // 不合格的结果属于正在拧紧的螺栓和重复次数
+            let passed = wrench_task.passed_count();
+            let (bolt, repeat) = wrench_task.bolt_position(passed);
+            let locked = wrench_task.locked;
+            let status = violations.is_empty() && !locked;
+            let finished_bolts = (passed + status as usize) / wrench_task.repeat_times();
+            let mut desc = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            if locked {
+                desc.push("任务已锁定, 结果不计入".to_string());
+            }
 
-                if joints_set.contains(&(recv.joint_id as i32)) {
-                    debug!("重复的joint_id: {}", recv.joint_id);
-                    continue;
-                }
+            // 以上一个 joint 的拧紧时间作为本次的开始时间
+            let end_date = wrench_time(recv.unix_time);
+            let start_date = wrench_task.last_report.min(end_date);
 
-                let tmp = JointData {
-                    joint_id: recv.joint_id as i32,
-                    unix_time: recv.unix_time,
-                    flag: recv.flag.clone(),
-                    torque: recv.torque,
-                    angle: recv.angle,
-                };
+            tx.send(ResponseAction::TaskFinished(FinishedInfo {
+                msg_id: wrench_task.msg_id.clone(),
+                wrench_serial: self.serial,
+                task_id: wrench_task.redis_task_id.clone(),
+                task_detail_id: wrench_task.redis_task_detail_id.clone(),
+                task_sub_id: format!("{}-{}", bolt, repeat),
+                bolt,
+                repeat,
+                finished_bolts,
+                bolt_num: wrench_task.joints_task.bolt_num as usize,
+                torque: scale_down(recv.torque, 3),
+                angle: scale_down(recv.angle as i32, 1),
+                status,
+                desc: desc.join(", "),
+                start_date,
+                end_date,
+            }))?;
 
-                let param = AssertOkParam {
-                    torque: wrench_task.joints_task.torque,
-                    torque_lower_tol: wrench_task.joints_task.torque_lower_tol,
-                    torque_upper_tol: wrench_task.joints_task.torque_upper_tol,
-                    angle: wrench_task.joints_task.angle,
-                    angle_lower_tol: wrench_task.joints_task.angle_lower_tol,
-                    angle_upper_tol: wrench_task.joints_task.angle_upper_tol,
-                    control_mode: wrench_task.joints_task.control_mode,
-                };
+            wrench_task.last_report = end_date;
+            if locked {
+                continue;
+            }
+            wrench_task.joints_recv.push(tmp);
 
-                tx.send(ResponseAction::TaskFinished(FinishedInfo {
-                    msg_id: wrench_task.msg_id.clone(),
-                    wrench_serial: self.serial,
-                    task_id: wrench_task.redis_task_id.clone(),
-                    task_detail_id: wrench_task.redis_task_detail_id.clone(),
-                    task_sub_id: wrench_task
-                        .joints_recv
-                        .iter()
-                        .filter(|x| assert_ok(&param, x))
-                        .count()
-                        .to_string(),
-                    torque: scale_down(recv.torque, 3),
-                    angle: scale_down(recv.angle as i32, 1),
-                    status: assert_ok(&param, &tmp),
-                    start_date: wrench_task.last_report,
-                    end_date: chrono::Local::now(),
-                }))?;
+            if status {
+                // 合格后进入下一个螺栓时才清零, 同一螺栓的重复拧紧之间不清零
+                if wrench_task.bolt_position(passed + 1).0 != bolt {
+                    wrench_task.bolt_nok = 0;
+                }
+            } else {
+                wrench_task.bolt_nok = wrench_task.bolt_nok.saturating_add(1);
+                let max_nok = wrench_task.joints_task.max_nok;
+                if max_nok > 0 && wrench_task.bolt_nok >= max_nok {
+                    let action = wrench_task.joints_task.nok_action;
+                    info!(
+                        "扳手 {:X} 任务 {} 的第 {} 个螺栓已 {} 次不合格, 处理方式: {:?}",
+                        self.serial, wrench_task.redis_task_id, bolt, wrench_task.bolt_nok, action
+                    );
+                    wrench_task.locked = true;
+                    tx.send(ResponseAction::NokLimit(NokLimitInfo {
+                        msg_id: wrench_task.msg_id.clone(),
+                        wrench_serial: self.serial,
+                        task_id: wrench_task.redis_task_id.clone(),
+                        task_detail_id: wrench_task.redis_task_detail_id.clone(),
+                        bolt,
+                        nok_count: wrench_task.bolt_nok,
+                        action,
+                    }))?;
+                    if action == ReworkAction::Abort {
+                        aborted = Some(wrench_task.wrench_task_id);
+                    }
+                }
+            }
 
-                wrench_task.last_report = chrono::Local::now();
-                wrench_task.joints_recv.push(tmp);
-                joints_set.insert(recv.joint_id as i32);
-                self.total_joints += 1;
+            debug!(
+                "扳手 {:X} 收集到任务数据: {:?}, 状态为: {:?}",
+                self.serial, recv, self.status
+            );
+        }
 
-                debug!(
-                    "扳手 {:X} 收集到任务数据: {:?}, 状态为: {:?}",
-                    self.serial, recv, self.status
-                );
+        // 中止的任务直接结束, 由 interval_update 开始下一个任务
+        if let Some(task_id) = aborted {
+            if self.current_task.as_ref().map(|t| t.wrench_task_id) == Some(task_id) {
+                let task = self.current_task.take().unwrap();
+                self.task_event(&task, TaskStage::Aborted, tx);
+                self.finish_task(task);
+                self.status = WrenchStatus::Connected;
             }
         }
 
@@ -255,25 +965,124 @@ impl WrenchContext {
                     error!("扳手 {:X} 发送任务失败: {:?}", self.serial, e);
                 }
             }
-            RequiredAction::TaskCancel((_, task_id)) => {
-                if let Some(wrench_task) = &self.current_task {
-                    if wrench_task.redis_task_id == task_id {
-                        self.clear_task(com_sender);
-                        self.current_task.take();
-                        self.status = WrenchStatus::Connected;
-                    }
-                }
-                self.retain_task(task_id);
+            RequiredAction::TaskCancel(cancel_info) => {
+                self.cancel_task(cancel_info, com_sender, redis_sender);
                 debug!("扳手 {:X} 取消任务", self.serial);
                 debug!("扳手 {:X} 当前任务: {:?}", self.serial, self.current_task);
                 debug!("扳手 {:X} 任务列表: {:?}", self.serial, self.pending_task);
             }
+            RequiredAction::TaskRelease(mut release_info) => {
+                let task_id = release_info.task_id.clone();
+                let desc = match self
+                    .current_task
+                    .as_mut()
+                    .filter(|t| t.redis_task_id == task_id && t.locked)
+                {
+                    Some(task) if task.joints_task.nok_action == ReworkAction::Release => {
+                        info!("扳手 {:X} 的任务 {} 已被主管解除锁定", self.serial, task_id);
+                        task.locked = false;
+                        task.bolt_nok = 0;
+                        release_info.status = true;
+                        "任务已解除锁定"
+                    }
+                    Some(_) => {
+                        error!(
+                            "扳手 {:X} 的任务 {} 不允许解除锁定, 只能取消任务",
+                            self.serial, task_id
+                        );
+                        "任务不允许解除锁定, 只能取消任务"
+                    }
+                    None => {
+                        debug!("扳手 {:X} 没有被锁定的任务 {}", self.serial, task_id);
+                        "没有被锁定的任务"
+                    }
+                };
+                release_info.desc = desc.to_string();
+                let released = release_info.status;
+                if let Err(e) = redis_sender.send(ResponseAction::ReleaseStatus(release_info)) {
+                    error!("扳手 {:X} 发送解除锁定结果失败: {:?}", self.serial, e);
+                }
+                // 与抢占后恢复相同, 按剩余数量重新下发任务
+                if released {
+                    self.send_task(com_sender, redis_sender);
+                }
+            }
+            RequiredAction::Beep(beep_info) => {
+                debug!("向Mac地址为: {:X?} 的扳手发送蜂鸣信号", self.mac);
+                self.send_request(
+                    RequestKind::Beep(beep_info.msg_id),
+                    12,
+                    0u8,
+                    WRCPayload::Beep,
+                    com_sender,
+                );
+            }
+            RequiredAction::TaskReorder(reorder_info) => {
+                self.reorder_task(reorder_info, com_sender, redis_sender)
+            }
+            RequiredAction::ClearJoints(_) => self.clear_task(com_sender),
             _ => {}
         }
     }
 
-    fn retain_task(&mut self, task_id: String) {
-        self.pending_task.retain(|x| x.redis_task_id != task_id);
+    /// 取消匹配的任务, 当前任务被取消时等待扳手确认清空后再应答
+    fn cancel_task(
+        &mut self,
+        mut cancel_info: CancelInfo,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        let station_wide = cancel_info.wrench_serial.is_none();
+        cancel_info.wrench_serial = Some(self.serial);
+
+        let cancelled = self
+            .pending_task
+            .iter()
+            .filter(|x| cancel_matches(&cancel_info, x))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.pending_task
+            .retain(|x| !cancel_matches(&cancel_info, x));
+        for task in cancelled.iter() {
+            self.task_event(task, TaskStage::Cancelled, redis_sender);
+        }
+        cancel_info.cancelled = cancelled.len();
+
+        if matches!(&self.current_task, Some(x) if cancel_matches(&cancel_info, x)) {
+            let task = self.current_task.take().unwrap();
+            self.clear_task(com_sender);
+            self.task_event(&task, TaskStage::Cancelled, redis_sender);
+            if matches!(self.status, WrenchStatus::Working) {
+                self.status = WrenchStatus::Connected;
+            }
+            cancel_info.cancelled += 1;
+            info!(
+                "扳手 {:X} 的当前任务 {} 已取消, 等待扳手确认清空",
+                self.serial, task.redis_task_id
+            );
+            self.pending_cancels.push(cancel_info);
+            return;
+        }
+
+        if cancel_info.cancelled == 0 {
+            // 工位范围的取消只由有任务的扳手应答
+            if station_wide {
+                return;
+            }
+            cancel_info.status = cancel_info.target == CancelTarget::All;
+            cancel_info.desc = if cancel_info.status {
+                "没有需要取消的任务"
+            } else {
+                "没有找到需要取消的任务"
+            }
+            .to_string();
+        } else {
+            cancel_info.status = true;
+            cancel_info.desc = "任务已取消".to_string();
+        }
+        if let Err(e) = redis_sender.send(ResponseAction::CancelStatus(cancel_info)) {
+            error!("扳手 {:X} 发送取消结果失败: {:?}", self.serial, e);
+        }
     }
 
     fn append_task(
@@ -310,10 +1119,12 @@ impl WrenchContext {
         } else {
             0
         });
+        // 等待队列按优先级排列, 最后一个任务不一定是最新的
         last_task_id = last_task_id.max(
             self.pending_task
-                .back()
+                .iter()
                 .map(|x| x.wrench_task_id)
+                .max()
                 .unwrap_or(0),
         );
 
@@ -369,14 +1180,51 @@ impl WrenchContext {
                 Ok(x) => x,
                 Err(_) => continue,
             };
+            // 监控量与控制量相反, 扭矩的精度为 0.001, 角度的精度为 0.1
+            let monitor_scale = if control_mode == 1 { 3 } else { 1 };
+            let monitor_min = match scale_up_optional(&task.monitor, monitor_scale) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let monitor_max = match scale_up_optional(&task.target, monitor_scale) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            // 监控角度时窗口必须在扳手角度的范围内
+            if control_mode != 1
+                && [monitor_min, monitor_max]
+                    .iter()
+                    .flatten()
+                    .any(|x| i16::try_from(*x).is_err())
+            {
+                error!("任务 {} 的角度监控窗口超出范围", task.task_id);
+                continue;
+            }
+            let max_nok = match &task.max_nok_count {
+                Some(x) => match x.parse::<u16>() {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                },
+                None => self.config.max_nok_per_bolt,
+            };
+            let nok_action = task.nok_action.unwrap_or(self.config.nok_action);
+            let priority = match &task.priority {
+                Some(x) => match x.parse::<u8>() {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                },
+                None => 0,
+            };
 
             last_task_id += 1;
             need_push.push(WrenchTask {
                 wrench_task_id: last_task_id,
                 redis_task_id: task.task_id,
                 redis_task_detail_id: task.task_detail_id,
+                station_ip: task.station_ip,
                 msg_id: msg_id.clone(),
                 last_report: chrono::Local::now(),
+                delivered: false,
                 joints_task: JointTask {
                     torque,
                     torque_angle_start,
@@ -390,14 +1238,26 @@ impl WrenchContext {
                     work_mode,
                     unit,
                     bolt_num,
+                    monitor_min,
+                    monitor_max,
+                    max_nok,
+                    nok_action,
                 },
                 joints_recv: Vec::new(),
+                bolt_nok: 0,
+                locked: false,
+                priority,
+                send_failed: false,
             });
         }
 
         if need_push.len() == origin_tasks_len {
             task_info.status = true;
-            self.pending_task.extend(need_push.into_iter());
+            for task in need_push {
+                let index = self.insert_task(task, false);
+                self.task_event(&self.pending_task[index], TaskStage::Queued, redis_sender);
+            }
+            self.check_preempt(com_sender);
         }
 
         redis_sender.send(ResponseAction::TaskStatus(task_info))?;
@@ -406,17 +1266,6 @@ impl WrenchContext {
     }
 
     fn clear_task(&mut self, com_sender: &mpsc::Sender<WRCPacket>) {
-        let mut flag = WRCPacketFlag(0);
-        flag.set_direction(true);
-        flag.set_type(10);
-        let clear_packet = WRCPacket {
-            sequence_id: 0,
-            mac: self.mac,
-            flag,
-            payload_len: 0u8,
-            payload: WRCPayload::ClearJointData,
-        };
-        self.last_send_id = 0;
         self.total_joints = 0;
         if let (WrenchStatus::Working, Some(current)) = (&self.status, &mut self.current_task) {
             for joint in current.joints_recv.iter_mut() {
@@ -425,9 +1274,13 @@ impl WrenchContext {
         }
 
         debug!("向Mac地址为: {:X?} 的扳手发送清空任务信号", self.mac);
-        if let Err(e) = com_sender.send(clear_packet) {
-            error!("扳手 {:X} 发送清空任务失败: {:?}", self.serial, e);
-        }
+        self.send_request(
+            RequestKind::ClearJointData,
+            10,
+            0u8,
+            WRCPayload::ClearJointData,
+            com_sender,
+        );
     }
 
     pub fn interval_update(
@@ -465,88 +1318,98 @@ impl WrenchContext {
         if self.last_report.elapsed() > std::time::Duration::from_secs(120) {
             self.last_report = Instant::now();
             query_energy(self.mac, com_sender).ok();
+            let estimate = self.battery_estimate();
             if let Err(e) = redis_sender.send(ResponseAction::BasicStatus(BasicInfo {
                 wrench_serial: self.serial,
                 voltage: self.voltage.unwrap_or_default() as u32,
-                storage: self.total_joints as u32,
+                storage: self.total_joints,
                 use_time: self.online_time,
+                battery_percent: estimate.map(|(percent, _)| percent),
+                remaining_joints: estimate.map(|(_, joints)| joints),
             })) {
                 error!("扳手 {:X} 无法发送状态: {:?}", self.serial, e);
             }
         }
 
-        if self.last_send.elapsed() > std::time::Duration::from_secs(5) {
+        if self.last_clock_check.elapsed()
+            > Duration::from_secs(self.config.clock_check_interval_secs)
+        {
+            self.last_clock_check = Instant::now();
+            query_timing(self.mac, com_sender).ok();
+        }
+
+        if self.last_network_check.elapsed()
+            > Duration::from_secs(self.config.network_check_interval_secs)
+        {
+            self.last_network_check = Instant::now();
+            query_network(self.mac, com_sender).ok();
+        }
+
+        // 工作中需要尽快拿到拧紧结果, 空闲时按退避后的间隔查询
+        let joint_poll_interval = match self.status {
+            WrenchStatus::Working => Duration::from_millis(self.config.joint_poll_working_ms),
+            _ => self.joint_poll_interval,
+        };
+        if self.last_send.elapsed() > joint_poll_interval {
             self.last_send = Instant::now();
-            let mut wrc_flag = WRCPacketFlag(0);
-            wrc_flag.set_direction(true);
-            wrc_flag.set_type(9);
-            let get_joint_packet = WRCPacket {
-                sequence_id: self.last_send_id.saturating_add(1),
-                mac: self.mac,
-                flag: wrc_flag,
-                payload_len: 3u8,
-                payload: WRCPayload::GetJointData(WRCPayloadGetJointData {
-                    joint_id_start: self.total_joints,
-                    joint_count: 1,
-                }),
-            };
-            self.last_send_id = self.last_send_id.saturating_add(1);
             debug!(
-                "向 {:X} 扳手发送查询请求, 该扳手的状态为 {:?}",
+                "向 {:X} 扳手查询存储的 joint 数量, 该扳手的状态为 {:?}",
                 self.serial, self.status
             );
-            if let Err(e) = com_sender.send(get_joint_packet) {
-                error!("扳手 {:X} 无法发送数据 {:?}", self.serial, e);
+            query_generic(self.mac, com_sender).ok();
+        }
+
+        self.retransmit_update(com_sender, redis_sender);
+
+        if let Some(started) = self.draining {
+            if started.elapsed() > Duration::from_secs(self.config.reconnect_drain_timeout_secs) {
+                error!("扳手 {:X} 收取断线期间的数据超时", self.serial);
+                self.finish_drain(com_sender, redis_sender);
             }
         }
 
         if let Some(wrench_task) = &self.current_task {
-            let param = AssertOkParam {
-                torque: wrench_task.joints_task.torque,
-                torque_lower_tol: wrench_task.joints_task.torque_lower_tol,
-                torque_upper_tol: wrench_task.joints_task.torque_upper_tol,
-                angle: wrench_task.joints_task.angle,
-                angle_lower_tol: wrench_task.joints_task.angle_lower_tol,
-                angle_upper_tol: wrench_task.joints_task.angle_upper_tol,
-                control_mode: wrench_task.joints_task.control_mode,
-            };
-            let passed_count = wrench_task
-                .joints_recv
-                .iter()
-                .filter(|x| assert_ok(&param, x))
-                .count();
-            let target_count = wrench_task.joints_task.bolt_num as usize;
+            let passed_count = wrench_task.passed_count();
+            let target_count = wrench_task.required_count();
 
-            if passed_count == target_count {
+            if passed_count >= target_count {
                 let tmp = self.current_task.take().unwrap();
-                self.finished_task.push(tmp);
+                self.task_event(&tmp, TaskStage::Completed, redis_sender);
+                self.finish_task(tmp);
                 self.status = WrenchStatus::Connected;
             }
         }
 
-        if matches!(self.status, WrenchStatus::Connected) {
-            if let Some(wrench_task) = self.pending_task.pop_front() {
+        if matches!(self.status, WrenchStatus::Connected) && self.draining.is_none() {
+            if let Some(mut wrench_task) = self.pending_task.pop_front() {
+                wrench_task.last_report = chrono::Local::now();
                 self.current_task = Some(wrench_task);
                 self.status = WrenchStatus::Working;
-                self.send_task(com_sender);
+                self.current_task_event(TaskStage::Started, redis_sender);
+                self.send_task(com_sender, redis_sender);
             }
         }
     }
 
     pub fn mac_reconnect(
         &mut self,
+        port: &str,
         mac: u32,
         com_sender: &mpsc::Sender<WRCPacket>,
         redis_sender: &mpsc::Sender<ResponseAction>,
     ) {
+        self.port = port.to_string();
         self.mac = mac;
         self.last_recv = Instant::now();
+        // 重连后立即核对扳手存储的 joint 数量
+        query_generic(mac, com_sender).ok();
 
         if matches!(self.status, WrenchStatus::Disconnected) {
             if self.current_task.is_some() {
+                // 先收取断线期间存储的数据, 清空扳手数据会丢失这些结果
+                info!("扳手 {:X} 重连, 开始收取断线期间的拧紧数据", self.serial);
                 self.status = WrenchStatus::Working;
-                self.clear_task(com_sender);
-                self.send_task(com_sender);
+                self.draining = Some(Instant::now());
             } else {
                 self.status = WrenchStatus::Connected;
             }
@@ -561,63 +1424,113 @@ impl WrenchContext {
         }
     }
 
-    fn send_task(&mut self, com_sender: &mpsc::Sender<WRCPacket>) {
-        if let Some(wrench_task) = &self.current_task {
+    fn send_task(
+        &mut self,
+        com_sender: &mpsc::Sender<WRCPacket>,
+        redis_sender: &mpsc::Sender<ResponseAction>,
+    ) {
+        if let Some(wrench_task) = self.current_task.as_mut() {
+            wrench_task.delivered = false;
+            wrench_task.send_failed = false;
+            self.last_task_send = Instant::now();
+
             let mut task_flag = WRCPayloadSetJointFlag(0);
             task_flag.set_mode(wrench_task.joints_task.control_mode);
             task_flag.set_method(wrench_task.joints_task.work_mode);
             task_flag.set_unit(wrench_task.joints_task.unit);
 
-            let mut wrc_flag = WRCPacketFlag(0);
-            wrc_flag.set_direction(true);
-            wrc_flag.set_type(7);
-            let task_packet = WRCPacket {
-                sequence_id: self.last_send_id.saturating_add(1),
-                mac: self.mac,
-                flag: wrc_flag,
-                payload_len: 33u8,
-                payload: WRCPayload::SetJoint(WRCPayloadSetJoint {
-                    torque_setpoint: wrench_task.joints_task.torque,
-                    torque_angle_start: wrench_task.joints_task.torque_angle_start,
-                    torque_upper_tol: wrench_task.joints_task.torque_upper_tol,
-                    torque_lower_tol: wrench_task.joints_task.torque_lower_tol,
-                    angle: wrench_task.joints_task.angle,
-                    angle_upper_tol: wrench_task.joints_task.angle_upper_tol,
-                    angle_lower_tol: wrench_task.joints_task.angle_lower_tol,
-                    fdt: -1,
-                    fda: -1,
-                    task_repeat_times: wrench_task.joints_task.bolt_num as u16,
-                    task_id: wrench_task.wrench_task_id,
-                    flag: task_flag,
-                }),
-            };
+            let task_id = wrench_task.wrench_task_id;
+            let payload = WRCPayload::SetJoint(WRCPayloadSetJoint {
+                torque_setpoint: wrench_task.joints_task.torque,
+                torque_angle_start: wrench_task.joints_task.torque_angle_start,
+                torque_upper_tol: wrench_task.joints_task.torque_upper_tol,
+                torque_lower_tol: wrench_task.joints_task.torque_lower_tol,
+                angle: wrench_task.joints_task.angle,
+                angle_upper_tol: wrench_task.joints_task.angle_upper_tol,
+                angle_lower_tol: wrench_task.joints_task.angle_lower_tol,
+                fdt: -1,
+                fda: -1,
+                // 重新下发时只需要完成剩余的数量
+                task_repeat_times: wrench_task
+                    .required_count()
+                    .saturating_sub(wrench_task.passed_count())
+                    .max(1) as u16,
+                task_id: wrench_task.wrench_task_id,
+                flag: task_flag,
+            });
 
-            if let Err(e) = com_sender.send(task_packet) {
-                error!("扳手 {:X} 无法发送数据 {:?}", self.serial, e);
-            }
+            self.send_request(RequestKind::SetJoint(task_id), 7, 33u8, payload, com_sender);
+            self.current_task_event(TaskStage::Sent, redis_sender);
+        }
+    }
+}
+
+/// 将扳手记录的时间戳转换为本地时间, 时间戳无效时使用主机时间
+fn wrench_time(unix_time: u32) -> DateTime<Local> {
+    match Local.timestamp_opt(unix_time as i64, 0) {
+        chrono::LocalResult::Single(t) if unix_time != 0 => t,
+        _ => chrono::Local::now(),
+    }
+}
+
+fn cancel_matches(cancel_info: &CancelInfo, task: &WrenchTask) -> bool {
+    let station_matched = match &cancel_info.station_ip {
+        Some(ip) => &task.station_ip == ip,
+        None => true,
+    };
+    station_matched
+        && match &cancel_info.target {
+            CancelTarget::Task(task_id) => &task.redis_task_id == task_id,
+            CancelTarget::Detail(detail_id) => &task.redis_task_detail_id == detail_id,
+            CancelTarget::All => true,
         }
+}
+
+impl WrenchTask {
+    /// 已经合格的 joint 数量
+    pub fn passed_count(&self) -> usize {
+        self.joints_recv
+            .iter()
+            .filter(|x| evaluate(&self.joints_task, x.torque, x.angle).is_empty())
+            .count()
+    }
+
+    /// 每个螺栓需要拧紧合格的次数
+    pub fn repeat_times(&self) -> usize {
+        self.joints_task.task_repeat_times.max(1) as usize
+    }
+
+    /// 完成任务需要的合格次数
+    pub fn required_count(&self) -> usize {
+        self.joints_task.bolt_num as usize * self.repeat_times()
+    }
+
+    /// 已经合格 passed 次时, 下一次拧紧对应的螺栓和重复次数, 均从 1 开始
+    pub fn bolt_position(&self, passed: usize) -> (usize, usize) {
+        let repeat_times = self.repeat_times();
+        (passed / repeat_times + 1, passed % repeat_times + 1)
     }
 }
 
-struct AssertOkParam {
-    torque: i32,
-    torque_lower_tol: i32,
-    torque_upper_tol: i32,
-    angle: i16,
-    angle_lower_tol: i16,
-    angle_upper_tol: i16,
-    control_mode: u8,
+pub fn scale_down(mut int: i32, mut scale: i32) -> String {
+    let mut frac = 0;
+    let mut level = 0;
+
+    while scale > 0 {
+        frac += i32::pow(10, level) * (int % 10);
+        int /= 10;
+        scale -= 1;
+        level += 1;
+    }
+
+    format!("{}.{}", int, frac.abs())
 }
 
-fn assert_ok(param: &AssertOkParam, data: &JointData) -> bool {
-    let torque_range = param.torque.saturating_sub(param.torque_lower_tol)
-        ..=param.torque.saturating_add(param.torque_upper_tol);
-    let angle_range = param.angle.saturating_sub(param.angle_lower_tol)
-        ..=param.angle.saturating_add(param.angle_upper_tol);
-    match param.control_mode {
-        0 => torque_range.contains(&data.torque),
-        1 => angle_range.contains(&data.angle),
-        _ => torque_range.contains(&data.torque) && angle_range.contains(&data.angle),
+/// 空字符串表示没有配置该值
+fn scale_up_optional(s: &str, scale: i32) -> anyhow::Result<Option<i32>> {
+    match s.trim() {
+        "" => Ok(None),
+        s => scale_up(s, scale).map(Some),
     }
 }
 
@@ -644,3 +1557,664 @@ fn scale_up(s: &str, mut scale: i32) -> anyhow::Result<i32> {
     }
     Ok(int_side)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use chrono::Local;
+
+    use super::{cancel_matches, JointData, JointTask, WrenchContext, WrenchStatus, WrenchTask};
+    use crate::{
+        app_data::{ReworkAction, WrenchConfig},
+        hardware::message::wrc::{
+            WRCPacket, WRCPacketFlag, WRCPayload, WRCPayloadInfoGeneric, WRCPayloadInlineJointData,
+            WRCPayloadInlineJointDataFlag, WRCPayloadStatusReport, WRCStatus,
+        },
+        message::{
+            CancelInfo, CancelTarget, ReleaseInfo, ReorderInfo, RequiredAction, ResponseAction,
+            TaskStage,
+        },
+        redis::message::TaskRequestMsg,
+    };
+
+    fn task(wrench_task_id: u16, redis_task_id: &str, bolt_num: u32, repeat: u16) -> WrenchTask {
+        WrenchTask {
+            wrench_task_id,
+            redis_task_id: redis_task_id.to_string(),
+            redis_task_detail_id: format!("{}-1", redis_task_id),
+            msg_id: "msg".to_string(),
+            last_report: Local::now(),
+            delivered: true,
+            joints_task: JointTask {
+                torque: 20000,
+                torque_angle_start: 0,
+                torque_upper_tol: 1000,
+                torque_lower_tol: 1000,
+                angle: 0,
+                angle_upper_tol: 0,
+                angle_lower_tol: 0,
+                task_repeat_times: repeat,
+                bolt_num,
+                control_mode: 0,
+                work_mode: 0,
+                unit: 0,
+                monitor_min: None,
+                monitor_max: None,
+                max_nok: 0,
+                nok_action: Default::default(),
+            },
+            joints_recv: vec![],
+            bolt_nok: 0,
+            locked: false,
+            station_ip: "10.0.0.1".to_string(),
+            priority: 0,
+            send_failed: false,
+        }
+    }
+
+    fn joint_data(joint_id: i32, torque: i32) -> JointData {
+        JointData {
+            joint_id,
+            unix_time: 0,
+            flag: WRCPayloadInlineJointDataFlag(0),
+            torque,
+            angle: 0,
+        }
+    }
+
+    fn joint(joint_id: u16, task_id: u16, torque: i32) -> WRCPayloadInlineJointData {
+        WRCPayloadInlineJointData {
+            joint_id,
+            task_id,
+            unix_time: 0,
+            flag: WRCPayloadInlineJointDataFlag(0),
+            torque,
+            angle: 0,
+        }
+    }
+
+    fn packet(payload: WRCPayload) -> WRCPacket {
+        WRCPacket {
+            sequence_id: 0,
+            mac: 1,
+            flag: WRCPacketFlag(0),
+            payload_len: 0,
+            payload,
+        }
+    }
+
+    fn info_generic(joint_count: u16) -> WRCPacket {
+        packet(WRCPayload::InfoGeneric(WRCPayloadInfoGeneric {
+            joint_count,
+            last_server_packet_seqid: 0,
+        }))
+    }
+
+    /// 下发任务时设置的重复次数
+    fn set_joint_repeats(packets: &[WRCPacket]) -> Vec<u16> {
+        packets
+            .iter()
+            .filter_map(|p| match &p.payload {
+                WRCPayload::SetJoint(set_joint) => Some(set_joint.task_repeat_times),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn wrench() -> WrenchContext {
+        WrenchContext::new("COM1", 1, 0xABCD, WrenchConfig::default())
+    }
+
+    #[test]
+    fn reconnect_drain_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, _rx) = mpsc::channel();
+        let mut wrench = wrench();
+        let mut current = task(1, "task", 4, 1);
+        current.joints_recv.push(joint_data(0, 20000));
+        wrench.current_task = Some(current);
+        wrench.total_joints = 1;
+        wrench.status = WrenchStatus::Disconnected;
+
+        wrench.mac_reconnect("COM1", 1, &com_tx, &tx);
+        assert!(wrench.draining.is_some());
+
+        // 扳手在断线期间又存储了两个结果, 收取完之前不能清空和重新下发
+        wrench.com_update(&info_generic(3), &com_tx, &tx);
+        let packets = com_rx.try_iter().collect::<Vec<_>>();
+        assert!(set_joint_repeats(&packets).is_empty());
+        assert!(packets
+            .iter()
+            .any(|p| matches!(p.payload, WRCPayload::GetJointData(_))));
+
+        let data = vec![joint(1, 1, 20000), joint(2, 1, 20000)];
+        wrench.com_update(&packet(WRCPayload::JointData(data)), &com_tx, &tx);
+        let packets = com_rx.try_iter().collect::<Vec<_>>();
+        assert!(set_joint_repeats(&packets).is_empty());
+        assert!(!packets
+            .iter()
+            .any(|p| matches!(p.payload, WRCPayload::ClearJointData)));
+        assert_eq!(wrench.current_task.as_ref().unwrap().passed_count(), 3);
+
+        // 再次核对数量一致后才清空扳手, 并且只下发剩余的数量
+        wrench.com_update(&info_generic(3), &com_tx, &tx);
+        assert!(wrench.draining.is_none());
+        let packets = com_rx.try_iter().collect::<Vec<_>>();
+        let clear = packets
+            .iter()
+            .position(|p| matches!(p.payload, WRCPayload::ClearJointData))
+            .unwrap();
+        let set_joint = packets
+            .iter()
+            .position(|p| matches!(p.payload, WRCPayload::SetJoint(_)))
+            .unwrap();
+        assert!(clear < set_joint);
+        assert_eq!(set_joint_repeats(&packets), vec![1]);
+        assert_eq!(wrench.total_joints, 0);
+    }
+
+    #[test]
+    fn append_task_monitor_range_test() {
+        let (com_tx, _com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut wrench = wrench();
+        let request = |target: &str| TaskRequestMsg {
+            task_id: "task".to_string(),
+            control_mode: "0".to_string(),
+            work_mode: "0".to_string(),
+            bolt_num: "1".to_string(),
+            repeat_count: "1".to_string(),
+            target: target.to_string(),
+            torque: "20".to_string(),
+            torque_deviation_up: "1".to_string(),
+            torque_deviation_down: "1".to_string(),
+            torque_angle_start: "5".to_string(),
+            angle: "0".to_string(),
+            angle_deviation_up: "0".to_string(),
+            angle_deviation_down: "0".to_string(),
+            unit: "0".to_string(),
+            ..Default::default()
+        };
+        let accepted = |rx: &mpsc::Receiver<ResponseAction>| {
+            rx.try_iter()
+                .filter_map(|action| match action {
+                    ResponseAction::TaskStatus(info) => Some(info.status),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // 角度监控值超出 i16 的范围时拒绝任务, 而不是截断成错误的限制
+        wrench
+            .append_task("msg".to_string(), vec![request("3276.8")], &com_tx, &tx)
+            .unwrap();
+        assert_eq!(accepted(&rx), vec![false]);
+        assert!(wrench.pending_task.is_empty());
+
+        wrench
+            .append_task("msg".to_string(), vec![request("3276.7")], &com_tx, &tx)
+            .unwrap();
+        assert_eq!(accepted(&rx), vec![true]);
+        assert_eq!(wrench.pending_task[0].joints_task.monitor_max, Some(32767));
+    }
+
+    #[test]
+    fn bolt_position_test() {
+        let mut wrench_task = task(1, "task", 2, 3);
+        assert_eq!(wrench_task.repeat_times(), 3);
+        assert_eq!(wrench_task.required_count(), 6);
+        assert_eq!(wrench_task.bolt_position(0), (1, 1));
+
+        // 每一行为本次拧紧的扭矩, 之后的合格次数, 下一次拧紧的螺栓和重复次数
+        let table = [
+            (20000, 1, (1, 2)),
+            (15000, 1, (1, 2)),
+            (20000, 2, (1, 3)),
+            (20000, 3, (2, 1)),
+            (25000, 3, (2, 1)),
+            (15000, 3, (2, 1)),
+            (20000, 4, (2, 2)),
+            (20000, 5, (2, 3)),
+            (20000, 6, (3, 1)),
+        ];
+        for (index, (torque, passed, position)) in table.into_iter().enumerate() {
+            wrench_task
+                .joints_recv
+                .push(joint_data(index as i32, torque));
+            assert_eq!(
+                wrench_task.passed_count(),
+                passed,
+                "第 {} 次拧紧",
+                index + 1
+            );
+            assert_eq!(
+                wrench_task.bolt_position(passed),
+                position,
+                "第 {} 次拧紧",
+                index + 1
+            );
+        }
+        assert!(wrench_task.passed_count() >= wrench_task.required_count());
+
+        // 重复次数为 0 时按 1 次计算
+        let wrench_task = task(1, "task", 2, 0);
+        assert_eq!(wrench_task.repeat_times(), 1);
+        assert_eq!(wrench_task.required_count(), 2);
+        assert_eq!(wrench_task.bolt_position(1), (2, 1));
+    }
+
+    /// 设置当前任务并让扳手处于工作状态
+    fn working(wrench_task: WrenchTask) -> WrenchContext {
+        let mut wrench = wrench();
+        wrench.current_task = Some(wrench_task);
+        wrench.status = WrenchStatus::Working;
+        wrench
+    }
+
+    fn release(
+        wrench: &mut WrenchContext,
+        com_tx: &mpsc::Sender<WRCPacket>,
+        tx: &mpsc::Sender<ResponseAction>,
+        rx: &mpsc::Receiver<ResponseAction>,
+    ) -> (bool, String) {
+        let release_info = ReleaseInfo {
+            msg_id: "release".to_string(),
+            wrench_serial: wrench.serial,
+            task_id: "task".to_string(),
+            status: false,
+            desc: "".to_string(),
+        };
+        wrench.redis_update(RequiredAction::TaskRelease(release_info), com_tx, tx);
+        rx.try_iter()
+            .find_map(|action| match action {
+                ResponseAction::ReleaseStatus(info) => Some((info.status, info.desc)),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn bolt_nok_reset_test() {
+        let (tx, _rx) = mpsc::channel();
+        let mut wrench = working(task(1, "task", 2, 2));
+
+        // 同一螺栓的重复拧紧之间不清零, 合格后进入下一个螺栓时才清零
+        let table = [
+            (15000, 1),
+            (20000, 1),
+            (15000, 2),
+            (20000, 0),
+            (15000, 1),
+            (20000, 1),
+        ];
+        for (joint_id, (torque, bolt_nok)) in table.into_iter().enumerate() {
+            wrench
+                .process_inline_joint_data(&[joint(joint_id as u16, 1, torque)], &tx)
+                .unwrap();
+            let current = wrench.current_task.as_ref().unwrap();
+            assert_eq!(current.bolt_nok, bolt_nok, "第 {} 次拧紧", joint_id + 1);
+        }
+    }
+
+    #[test]
+    fn nok_limit_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut wrench_task = task(1, "task", 2, 1);
+        wrench_task.joints_task.max_nok = 2;
+        let mut wrench = working(wrench_task);
+
+        let data = [joint(0, 1, 15000), joint(1, 1, 15000), joint(2, 1, 15000)];
+        wrench.process_inline_joint_data(&data, &tx).unwrap();
+        let current = wrench.current_task.as_ref().unwrap();
+        assert!(current.locked);
+        assert_eq!(current.bolt_nok, 2);
+        // 锁定之后的结果不再计入
+        assert_eq!(current.joints_recv.len(), 2);
+
+        let actions = rx.try_iter().collect::<Vec<_>>();
+        let limits = actions
+            .iter()
+            .filter_map(|action| match action {
+                ResponseAction::NokLimit(info) => Some(info),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(limits.len(), 1);
+        assert_eq!(limits[0].bolt, 1);
+        assert_eq!(limits[0].nok_count, 2);
+        assert_eq!(limits[0].action, ReworkAction::Lock);
+        let finished = actions
+            .iter()
+            .filter_map(|action| match action {
+                ResponseAction::TaskFinished(info) => Some(info),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(finished.len(), 3);
+        assert!(finished[2].desc.contains("任务已锁定"));
+
+        // 处理方式为 Lock 时不允许解除, 只能取消任务
+        let (status, desc) = release(&mut wrench, &com_tx, &tx, &rx);
+        assert!(!status);
+        assert_eq!(desc, "任务不允许解除锁定, 只能取消任务");
+        assert!(wrench.current_task.as_ref().unwrap().locked);
+        assert!(set_joint_repeats(&com_rx.try_iter().collect::<Vec<_>>()).is_empty());
+    }
+
+    #[test]
+    fn nok_release_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut wrench_task = task(1, "task", 2, 1);
+        wrench_task.joints_task.max_nok = 1;
+        wrench_task.joints_task.nok_action = ReworkAction::Release;
+        wrench_task.joints_recv.push(joint_data(0, 20000));
+        let mut wrench = working(wrench_task);
+        wrench.total_joints = 1;
+
+        let (status, desc) = release(&mut wrench, &com_tx, &tx, &rx);
+        assert!(!status);
+        assert_eq!(desc, "没有被锁定的任务");
+
+        wrench
+            .process_inline_joint_data(&[joint(1, 1, 15000)], &tx)
+            .unwrap();
+        assert!(wrench.current_task.as_ref().unwrap().locked);
+        rx.try_iter().for_each(drop);
+
+        // 解除锁定后按剩余数量重新下发任务
+        let (status, desc) = release(&mut wrench, &com_tx, &tx, &rx);
+        assert!(status);
+        assert_eq!(desc, "任务已解除锁定");
+        let current = wrench.current_task.as_ref().unwrap();
+        assert!(!current.locked);
+        assert_eq!(current.bolt_nok, 0);
+        assert_eq!(
+            set_joint_repeats(&com_rx.try_iter().collect::<Vec<_>>()),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn nok_abort_test() {
+        let (tx, rx) = mpsc::channel();
+        let mut wrench_task = task(1, "task", 2, 1);
+        wrench_task.joints_task.max_nok = 1;
+        wrench_task.joints_task.nok_action = ReworkAction::Abort;
+        let mut wrench = working(wrench_task);
+
+        wrench
+            .process_inline_joint_data(&[joint(0, 1, 15000)], &tx)
+            .unwrap();
+        assert!(wrench.current_task.is_none());
+        assert!(matches!(wrench.status, WrenchStatus::Connected));
+        assert_eq!(wrench.finished_task.len(), 1);
+        assert_eq!(wrench.finished_task[0].redis_task_id, "task");
+
+        let stages = rx
+            .try_iter()
+            .filter_map(|action| match action {
+                ResponseAction::TaskLifecycle(info) => Some(info.stage),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(stages, vec![TaskStage::Aborted]);
+    }
+
+    fn cancel(station_ip: Option<&str>, target: CancelTarget) -> CancelInfo {
+        CancelInfo {
+            msg_id: "cancel".to_string(),
+            wrench_serial: Some(0xABCD),
+            station_ip: station_ip.map(|ip| ip.to_string()),
+            target,
+            cancelled: 0,
+            status: false,
+            desc: "".to_string(),
+        }
+    }
+
+    fn cancel_statuses(rx: &mpsc::Receiver<ResponseAction>) -> Vec<CancelInfo> {
+        rx.try_iter()
+            .filter_map(|action| match action {
+                ResponseAction::CancelStatus(info) => Some(info),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cancel_matches_test() {
+        let a = task(1, "a", 1, 1);
+        let mut b = task(2, "b", 1, 1);
+        b.station_ip = "10.0.0.2".to_string();
+
+        let by_task = cancel(None, CancelTarget::Task("a".to_string()));
+        assert!(cancel_matches(&by_task, &a));
+        assert!(!cancel_matches(&by_task, &b));
+
+        let by_detail = cancel(None, CancelTarget::Detail("b-1".to_string()));
+        assert!(!cancel_matches(&by_detail, &a));
+        assert!(cancel_matches(&by_detail, &b));
+        assert!(!cancel_matches(
+            &cancel(None, CancelTarget::Detail("b".to_string())),
+            &b
+        ));
+
+        let all = cancel(None, CancelTarget::All);
+        assert!(cancel_matches(&all, &a));
+        assert!(cancel_matches(&all, &b));
+
+        // 指定工位时只匹配该工位的任务
+        let station = cancel(Some("10.0.0.2"), CancelTarget::All);
+        assert!(!cancel_matches(&station, &a));
+        assert!(cancel_matches(&station, &b));
+        let station_task = cancel(Some("10.0.0.2"), CancelTarget::Task("a".to_string()));
+        assert!(!cancel_matches(&station_task, &a));
+    }
+
+    #[test]
+    fn cancel_pending_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut wrench = working(task(1, "a", 1, 1));
+        wrench.pending_task.push_back(task(2, "b", 1, 1));
+
+        // 只取消等待中的任务时不需要清空扳手, 立即应答
+        wrench.cancel_task(
+            cancel(None, CancelTarget::Task("b".to_string())),
+            &com_tx,
+            &tx,
+        );
+        let statuses = cancel_statuses(&rx);
+        assert_eq!(statuses.len(), 1);
+        assert!(statuses[0].status);
+        assert_eq!(statuses[0].cancelled, 1);
+        assert!(wrench.pending_task.is_empty());
+        assert!(wrench.current_task.is_some());
+        assert_eq!(com_rx.try_iter().count(), 0);
+
+        wrench.cancel_task(
+            cancel(None, CancelTarget::Task("c".to_string())),
+            &com_tx,
+            &tx,
+        );
+        let statuses = cancel_statuses(&rx);
+        assert!(!statuses[0].status);
+        assert_eq!(statuses[0].desc, "没有找到需要取消的任务");
+
+        // 工位范围的取消没有匹配的任务时不应答
+        let mut station_wide = cancel(None, CancelTarget::Task("c".to_string()));
+        station_wide.wrench_serial = None;
+        wrench.cancel_task(station_wide, &com_tx, &tx);
+        assert!(cancel_statuses(&rx).is_empty());
+    }
+
+    #[test]
+    fn cancel_current_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+
+        for (status, confirmed, desc) in [
+            (WRCStatus::Success, true, "任务已取消"),
+            (WRCStatus::Failed, false, "扳手未确认清空任务"),
+        ] {
+            let mut wrench = working(task(1, "a", 1, 1));
+            wrench.cancel_task(cancel(None, CancelTarget::All), &com_tx, &tx);
+            assert!(wrench.current_task.is_none());
+            assert!(matches!(wrench.status, WrenchStatus::Connected));
+
+            // 扳手确认清空之前不应答
+            let actions = rx.try_iter().collect::<Vec<_>>();
+            assert!(!actions
+                .iter()
+                .any(|action| matches!(action, ResponseAction::CancelStatus(_))));
+            assert!(actions.iter().any(|action| matches!(
+                action,
+                ResponseAction::TaskLifecycle(info) if info.stage == TaskStage::Cancelled
+            )));
+            let clear = com_rx
+                .try_iter()
+                .find(|p| matches!(p.payload, WRCPayload::ClearJointData))
+                .unwrap();
+
+            let report = WRCPayloadStatusReport {
+                target_seqid: clear.sequence_id,
+                status: status as u16,
+            };
+            wrench.com_update(&packet(WRCPayload::StatusReport(report)), &com_tx, &tx);
+            let statuses = cancel_statuses(&rx);
+            assert_eq!(statuses.len(), 1);
+            assert_eq!(statuses[0].status, confirmed);
+            assert_eq!(statuses[0].desc, desc);
+            assert_eq!(statuses[0].cancelled, 1);
+            assert!(wrench.pending_cancels.is_empty());
+        }
+    }
+
+    fn prioritized(wrench_task_id: u16, redis_task_id: &str, priority: u8) -> WrenchTask {
+        let mut wrench_task = task(wrench_task_id, redis_task_id, 1, 1);
+        wrench_task.priority = priority;
+        wrench_task
+    }
+
+    fn pending_ids(wrench: &WrenchContext) -> Vec<String> {
+        wrench
+            .pending_task
+            .iter()
+            .map(|x| x.redis_task_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn insert_task_test() {
+        let mut wrench = wrench();
+        assert_eq!(wrench.insert_task(prioritized(1, "a", 0), false), 0);
+        assert_eq!(wrench.insert_task(prioritized(2, "b", 0), false), 1);
+        assert_eq!(wrench.insert_task(prioritized(3, "c", 2), false), 0);
+        // ahead 为 true 时排在同优先级任务之前, 否则排在之后
+        assert_eq!(wrench.insert_task(prioritized(4, "d", 0), true), 1);
+        assert_eq!(wrench.insert_task(prioritized(5, "e", 2), false), 1);
+        assert_eq!(wrench.insert_task(prioritized(6, "f", 1), false), 2);
+        assert_eq!(pending_ids(&wrench), vec!["c", "e", "f", "d", "a", "b"]);
+    }
+
+    #[test]
+    fn reorder_task_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut current = task(1, "current", 1, 1);
+        current.priority = 1;
+        let mut wrench = working(current);
+        for (id, priority) in [("a", 0), ("b", 0), ("c", 2)] {
+            let wrench_task_id = wrench.pending_task.len() as u16 + 2;
+            wrench.insert_task(prioritized(wrench_task_id, id, priority), false);
+        }
+        let mut reorder = |task_ids: &[&str]| {
+            let reorder_info = ReorderInfo {
+                msg_id: "reorder".to_string(),
+                task_ids: task_ids.iter().map(|id| id.to_string()).collect(),
+                ..Default::default()
+            };
+            wrench.redis_update(RequiredAction::TaskReorder(reorder_info), &com_tx, &tx);
+            rx.try_iter()
+                .find_map(|action| match action {
+                    ResponseAction::ReorderStatus(info) => Some(info),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // 有未知的任务时不调整队列
+        let info = reorder(&["a", "x"]);
+        assert!(!info.status);
+        assert_eq!(info.desc, "等待队列中没有任务 x");
+        assert_eq!(info.task_ids, vec!["c", "a", "b"]);
+
+        // 重复的任务只按第一次出现的位置调整
+        let info = reorder(&["b", "a", "b"]);
+        assert!(info.status);
+        assert_eq!(info.task_ids, vec!["b", "a", "c"]);
+        assert_eq!(info.wrench_serial, 0xABCD);
+
+        assert_eq!(pending_ids(&wrench), vec!["b", "a", "c"]);
+        assert!(wrench.pending_task.iter().all(|x| x.priority == 2));
+        // 提升后的任务高于当前任务, 开始收取当前任务的数据准备切换
+        assert!(wrench.draining.is_some());
+        assert!(com_rx
+            .try_iter()
+            .any(|p| matches!(p.payload, WRCPayload::GetInfo(_))));
+    }
+
+    #[test]
+    fn preempt_current_test() {
+        let (com_tx, com_rx) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        let mut current = task(1, "low", 3, 1);
+        current.joints_recv.push(joint_data(0, 20000));
+        let mut wrench = working(current);
+        wrench.total_joints = 1;
+        wrench.insert_task(prioritized(2, "high", 5), false);
+
+        // 抢占之前先收取当前任务的数据
+        wrench.check_preempt(&com_tx);
+        assert!(wrench.draining.is_some());
+        wrench.com_update(&info_generic(1), &com_tx, &tx);
+        assert!(wrench.draining.is_none());
+        assert!(wrench.current_task.is_none());
+        assert!(matches!(wrench.status, WrenchStatus::Connected));
+        assert_eq!(pending_ids(&wrench), vec!["high", "low"]);
+        let low = &wrench.pending_task[1];
+        assert_eq!(low.joints_recv.len(), 1);
+        assert!(!low.delivered);
+        assert!(rx.try_iter().any(|action| matches!(
+            action,
+            ResponseAction::TaskLifecycle(info) if info.stage == TaskStage::Preempted
+        )));
+        assert!(set_joint_repeats(&com_rx.try_iter().collect::<Vec<_>>()).is_empty());
+
+        wrench.interval_update(&com_tx, &tx);
+        assert_eq!(wrench.current_task.as_ref().unwrap().redis_task_id, "high");
+        assert_eq!(
+            set_joint_repeats(&com_rx.try_iter().collect::<Vec<_>>()),
+            vec![1]
+        );
+
+        // 高优先级任务完成后恢复被抢占的任务, 只下发剩余的数量
+        wrench
+            .current_task
+            .as_mut()
+            .unwrap()
+            .joints_recv
+            .push(joint_data(0, 20000));
+        wrench.interval_update(&com_tx, &tx);
+        let current = wrench.current_task.as_ref().unwrap();
+        assert_eq!(current.redis_task_id, "low");
+        assert_eq!(current.passed_count(), 1);
+        assert_eq!(
+            set_joint_repeats(&com_rx.try_iter().collect::<Vec<_>>()),
+            vec![2]
+        );
+    }
+}
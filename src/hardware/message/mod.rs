@@ -1,2 +1,11 @@
 pub mod usb;
 pub mod wrc;
+
+use self::{usb::USBLocalPacket, wrc::WRCPacket};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub enum ComPacket {
+    USBLocal(USBLocalPacket),
+    WRC(WRCPacket),
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
 pub struct USBLocalPayloadRFStatus {
     pub rssi: i8,
@@ -5,7 +7,7 @@ pub struct USBLocalPayloadRFStatus {
     pub rscp: i8,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct LoRaProperties {
     pub sf: u8,
     pub bw: u8,
@@ -52,10 +54,21 @@ impl TryFrom<Vec<u8>> for USBLocalPacket {
         let payload_len = value[1] as i8;
         let payload = &value[2..];
 
-        if payload.len() != payload_len as usize {
+        if payload.len() != value[1] as usize {
             return Err("Payload length mismatch");
         }
 
+        // 每种类型的负载长度固定, 索引之前先检查
+        let expected_len = match packet_type {
+            1 => 3,
+            2 => 10,
+            3 => 1,
+            _ => return Err("Invalid packet type"),
+        };
+        if payload.len() != expected_len {
+            return Err("Invalid payload length for packet type");
+        }
+
         let payload = match packet_type {
             1 => USBLocalPayload::RFStatus(USBLocalPayloadRFStatus {
                 rssi: payload[0] as i8,
@@ -114,3 +127,23 @@ impl TryInto<Vec<u8>> for USBLocalPacket {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{USBLocalPacket, USBLocalPayload};
+
+    #[test]
+    fn usb_local_length_test() {
+        // 负载长度与声明一致, 但小于该类型需要的长度
+        assert!(USBLocalPacket::try_from(vec![2, 1, 0x10]).is_err());
+        assert!(USBLocalPacket::try_from(vec![1, 2, 0x10, 0x20]).is_err());
+        assert!(USBLocalPacket::try_from(vec![3, 2, 0x01, 0x02]).is_err());
+        assert!(USBLocalPacket::try_from(vec![2, 0xFF, 0x10]).is_err());
+
+        let packet = USBLocalPacket::try_from(vec![1, 3, 0xB0, 0x05, 0x00]).unwrap();
+        assert!(matches!(
+            packet.payload,
+            USBLocalPayload::RFStatus(ref s) if s.rssi == -80 && s.snr == 5
+        ));
+    }
+}
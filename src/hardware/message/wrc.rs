@@ -64,24 +64,40 @@ pub enum WRCStatus {
     GetJointRangeError,
 }
 
-#[derive(Debug)]
+impl TryFrom<u16> for WRCStatus {
+    type Error = &'static str;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WRCStatus::None),
+            1 => Ok(WRCStatus::Success),
+            2 => Ok(WRCStatus::Failed),
+            3 => Ok(WRCStatus::JointsDeleted),
+            4 => Ok(WRCStatus::GetJointSuccess),
+            5 => Ok(WRCStatus::GetJointRangeError),
+            _ => Err("Unknown status"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoSerial {
     pub serial: [u8; 16],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoGeneric {
     pub joint_count: u16,
     pub last_server_packet_seqid: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoTiming {
     pub cpu_ticks: u32,
     pub wrench_time: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoNetworkPackets {
     pub collisions: u16,
     pub crc_errors: u16,
@@ -90,20 +106,21 @@ pub struct WRCPayloadInfoNetworkPackets {
     pub rx_unwanted_count: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoNetworkRF {
     pub rx_rssi: i8,
     pub rx_snr: i8,
     pub rx_rscp: i8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoNetwork {
     pub packets: WRCPayloadInfoNetworkPackets,
     pub rf: WRCPayloadInfoNetworkRF,
 }
 
 bitfield::bitfield! {
+    #[derive(Clone)]
     pub struct WRCPayloadInfoEnergyFlag(u8);
     impl Debug;
     u8;
@@ -117,13 +134,14 @@ bitfield::bitfield! {
     pub is_f7, set_f7: 7;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadInfoEnergy {
     pub flag: WRCPayloadInfoEnergyFlag,
     pub battery_voltage_mv: u16,
 }
 
 bitfield::bitfield! {
+    #[derive(Clone)]
     pub struct WRCPayloadInlineJointDataFlag(u8);
     impl Debug;
     u8;
@@ -134,12 +152,6 @@ bitfield::bitfield! {
     pub get_unit, set_unit: 7, 6;
 }
 
-impl Clone for WRCPayloadInlineJointDataFlag {
-    fn clone(&self) -> Self {
-        Self(self.0)
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct WRCPayloadInlineJointData {
     pub joint_id: u16,
@@ -151,6 +163,7 @@ pub struct WRCPayloadInlineJointData {
 }
 
 bitfield::bitfield! {
+    #[derive(Clone)]
     pub struct WRCPayloadSetJointFlag(u8);
     impl Debug;
     u8;
@@ -160,7 +173,7 @@ bitfield::bitfield! {
     pub get_unit, set_unit: 7, 6;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadSetJoint {
     pub torque_setpoint: i32,
     pub torque_angle_start: i32,
@@ -176,24 +189,25 @@ pub struct WRCPayloadSetJoint {
     pub flag: WRCPayloadSetJointFlag,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadSetWrenchTime {
     pub unix_time: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadGetJointData {
     pub joint_id_start: u16,
     pub joint_count: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadStatusReport {
     pub target_seqid: u16,
     pub status: u16,
 }
 
 bitfield::bitfield! {
+    #[derive(Clone)]
     pub struct WRCPayloadGetInfoFlag(u8);
     impl Debug;
     u8;
@@ -207,12 +221,12 @@ bitfield::bitfield! {
     pub is_f7, set_f7: 7;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPayloadGetInfo {
     pub flag: WRCPayloadGetInfoFlag,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum WRCPayload {
     InfoGeneric(WRCPayloadInfoGeneric),
     InfoSerial(WRCPayloadInfoSerial),
@@ -226,12 +240,13 @@ pub enum WRCPayload {
     ClearJointData,
     GetStatusReport,
     Beep,
-    JointData,
+    JointData(Vec<WRCPayloadInlineJointData>),
     StatusReport(WRCPayloadStatusReport),
     InlineJointData(Vec<WRCPayloadInlineJointData>),
 }
 
 bitfield::bitfield! {
+    #[derive(Clone)]
     pub struct WRCPacketFlag(u8);
     impl Debug;
     u8;
@@ -240,7 +255,7 @@ bitfield::bitfield! {
     pub get_type, set_type: 7, 2;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WRCPacket {
     pub sequence_id: u16,
     pub mac: u32,
@@ -249,6 +264,55 @@ pub struct WRCPacket {
     pub payload: WRCPayload,
 }
 
+/// JointData 与 InlineJointData 使用相同的布局, 每个 joint 占 15 字节
+fn parse_joint_data(payload: &[u8]) -> Vec<WRCPayloadInlineJointData> {
+    let mut payloads = vec![];
+    debug!("payload len: {}", payload.len());
+    debug!("payload: {:02X?}", payload);
+    for i in 0.. {
+        // 不足一个 joint 的剩余数据直接丢弃
+        if (i + 1) * 15 > payload.len() {
+            break;
+        }
+        let mut joint_id = [0u8; 2];
+        joint_id.copy_from_slice(&payload[(i * 15)..(i * 15 + 2)]);
+        let joint_id = u16::from_le_bytes(joint_id);
+        let mut task_id = [0u8; 2];
+        task_id.copy_from_slice(&payload[(i * 15 + 2)..(i * 15 + 4)]);
+        let task_id = u16::from_le_bytes(task_id);
+        let mut unix_time = [0u8; 4];
+        unix_time.copy_from_slice(&payload[(i * 15 + 4)..(i * 15 + 8)]);
+        let unix_time = u32::from_le_bytes(unix_time);
+        let flag = WRCPayloadInlineJointDataFlag(payload[i * 15 + 8]);
+        let mut torque = [0u8; 4];
+        torque.copy_from_slice(&payload[(i * 15 + 9)..(i * 15 + 13)]);
+        let torque = i32::from_le_bytes(torque);
+        let mut angle = [0u8; 2];
+        angle.copy_from_slice(&payload[(i * 15 + 13)..(i * 15 + 15)]);
+        let angle = i16::from_le_bytes(angle);
+        payloads.push(WRCPayloadInlineJointData {
+            joint_id,
+            task_id,
+            unix_time,
+            flag,
+            torque,
+            angle,
+        });
+    }
+    payloads
+}
+
+fn write_joint_data(result: &mut Vec<u8>, joint_data: &[WRCPayloadInlineJointData]) {
+    for joint in joint_data {
+        result.extend_from_slice(&joint.joint_id.to_le_bytes());
+        result.extend_from_slice(&joint.task_id.to_le_bytes());
+        result.extend_from_slice(&joint.unix_time.to_le_bytes());
+        result.push(joint.flag.0);
+        result.extend_from_slice(&joint.torque.to_le_bytes());
+        result.extend_from_slice(&joint.angle.to_le_bytes());
+    }
+}
+
 impl TryFrom<Vec<u8>> for WRCPacket {
     type Error = &'static str;
 
@@ -421,7 +485,7 @@ impl TryFrom<Vec<u8>> for WRCPacket {
             10 => WRCPayload::ClearJointData,
             11 => WRCPayload::GetStatusReport,
             12 => WRCPayload::Beep,
-            13 => WRCPayload::JointData,
+            13 => WRCPayload::JointData(parse_joint_data(payload)),
             14 => {
                 let mut target_seqid = [0u8; 2];
                 target_seqid.copy_from_slice(&payload[0..2]);
@@ -434,41 +498,7 @@ impl TryFrom<Vec<u8>> for WRCPacket {
                     status,
                 })
             }
-            15 => {
-                let mut payloads = vec![];
-                debug!("payload len: {}", payload.len());
-                debug!("payload: {:02X?}", payload);
-                for i in 0.. {
-                    if i * 15 >= payload.len() {
-                        break;
-                    }
-                    let mut joint_id = [0u8; 2];
-                    joint_id.copy_from_slice(&payload[(i * 15)..(i * 15 + 2)]);
-                    let joint_id = u16::from_le_bytes(joint_id);
-                    let mut task_id = [0u8; 2];
-                    task_id.copy_from_slice(&payload[(i * 15 + 2)..(i * 15 + 4)]);
-                    let task_id = u16::from_le_bytes(task_id);
-                    let mut unix_time = [0u8; 4];
-                    unix_time.copy_from_slice(&payload[(i * 15 + 4)..(i * 15 + 8)]);
-                    let unix_time = u32::from_le_bytes(unix_time);
-                    let flag = WRCPayloadInlineJointDataFlag(payload[i * 15 + 8]);
-                    let mut torque = [0u8; 4];
-                    torque.copy_from_slice(&payload[(i * 15 + 9)..(i * 15 + 13)]);
-                    let torque = i32::from_le_bytes(torque);
-                    let mut angle = [0u8; 2];
-                    angle.copy_from_slice(&payload[(i * 15 + 13)..(i * 15 + 15)]);
-                    let angle = i16::from_le_bytes(angle);
-                    payloads.push(WRCPayloadInlineJointData {
-                        joint_id,
-                        task_id,
-                        unix_time,
-                        flag,
-                        torque,
-                        angle,
-                    });
-                }
-                WRCPayload::InlineJointData(payloads)
-            }
+            15 => WRCPayload::InlineJointData(parse_joint_data(payload)),
             _ => {
                 return Err("Unknown packet type");
             }
@@ -547,7 +577,11 @@ impl TryInto<Vec<u8>> for WRCPacket {
             WRCPayload::ClearJointData => {}
             WRCPayload::GetStatusReport => {}
             WRCPayload::Beep => {}
-            WRCPayload::JointData => {}
+            WRCPayload::JointData(joint_data) => {
+                self.flag.set_variable_len(true);
+                result[6] = self.flag.0;
+                write_joint_data(&mut result, &joint_data);
+            }
             WRCPayload::StatusReport(status_report) => {
                 result.extend_from_slice(&status_report.target_seqid.to_le_bytes());
                 result.extend_from_slice(&status_report.status.to_le_bytes());
@@ -555,17 +589,52 @@ impl TryInto<Vec<u8>> for WRCPacket {
             WRCPayload::InlineJointData(inline_joint_data) => {
                 self.flag.set_variable_len(true);
                 result[6] = self.flag.0;
-                for inline_joint_data in inline_joint_data {
-                    result.extend_from_slice(&inline_joint_data.joint_id.to_le_bytes());
-                    result.extend_from_slice(&inline_joint_data.task_id.to_le_bytes());
-                    result.extend_from_slice(&inline_joint_data.unix_time.to_le_bytes());
-                    result.push(inline_joint_data.flag.0);
-                    result.extend_from_slice(&inline_joint_data.torque.to_le_bytes());
-                    result.extend_from_slice(&inline_joint_data.angle.to_le_bytes());
-                }
+                write_joint_data(&mut result, &inline_joint_data);
             }
         }
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        WRCPacket, WRCPacketFlag, WRCPayload, WRCPayloadInlineJointData,
+        WRCPayloadInlineJointDataFlag,
+    };
+
+    #[test]
+    fn joint_data_test() {
+        let mut flag = WRCPacketFlag(0);
+        flag.set_type(13);
+        let joint = WRCPayloadInlineJointData {
+            joint_id: 7,
+            task_id: 3,
+            unix_time: 1_700_000_000,
+            flag: WRCPayloadInlineJointDataFlag(1),
+            torque: -12345,
+            angle: 900,
+        };
+        let packet = WRCPacket {
+            sequence_id: 1,
+            mac: 0x1234,
+            flag,
+            payload_len: 30,
+            payload: WRCPayload::JointData(vec![joint.clone(), joint]),
+        };
+
+        let bytes: Vec<u8> = packet.try_into().unwrap();
+        let packet = WRCPacket::try_from(bytes).unwrap();
+        match packet.payload {
+            WRCPayload::JointData(joints) => {
+                assert_eq!(joints.len(), 2);
+                assert_eq!(joints[1].joint_id, 7);
+                assert_eq!(joints[1].task_id, 3);
+                assert_eq!(joints[1].torque, -12345);
+                assert_eq!(joints[1].angle, 900);
+            }
+            payload => panic!("错误的数据类型: {:?}", payload),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::{
+    collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Mutex,
+        mpsc,
     },
     thread::JoinHandle,
     time::Duration,
@@ -12,26 +13,28 @@ use crate::{
     message::{RequiredAction, ResponseAction},
 };
 
-use bus::Bus;
 use std::sync::Arc;
 use tracing::{error, info, span, Level};
 
-use super::com_process;
+use super::com_process::{self, registry::SharedRegistry};
 
 fn create_com_thread(
     exit_required: Arc<AtomicBool>,
     port: String,
     tx: mpsc::Sender<ResponseAction>,
-    bus: Arc<Mutex<Bus<RequiredAction>>>,
-) -> anyhow::Result<JoinHandle<()>> {
-    let mut bus = bus.lock().map_err(|err| anyhow::anyhow!(err.to_string()))?;
-    let rx = bus.add_rx();
-    drop(bus);
+    registry: SharedRegistry,
+    config: AppConfig,
+) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
+    let (action_tx, rx) = mpsc::channel::<RequiredAction>();
+    registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?
+        .workers
+        .insert(port.clone(), action_tx);
 
     let handle = std::thread::spawn(move || {
-        span!(Level::ERROR, "串口处理线程", port = %port).in_scope(|| {
-            com_process::com_process(exit_required, &port, tx, rx);
-        });
+        span!(Level::ERROR, "串口处理线程", port = %port)
+            .in_scope(|| com_process::com_process(exit_required, &port, tx, rx, registry, config))
     });
 
     Ok(handle)
@@ -40,16 +43,31 @@ fn create_com_thread(
 pub fn loop_query(
     exit_required: Arc<AtomicBool>,
     tx: mpsc::Sender<ResponseAction>,
-    bus: Arc<Mutex<Bus<RequiredAction>>>,
+    registry: SharedRegistry,
     config: AppConfig,
 ) {
-    let mut com_thread_handles: Vec<(String, JoinHandle<()>)> = vec![];
+    let mut com_thread_handles: HashMap<String, JoinHandle<anyhow::Result<()>>> = HashMap::new();
+    // 射频参数无法生效的网关不再使用, 避免与相邻产线互相干扰
+    let mut failed_ports = HashSet::new();
 
     info!("开始进行串口监听");
     while !exit_required.load(Ordering::Acquire) {
         std::thread::sleep(Duration::from_secs(1));
 
-        com_thread_handles.retain(|(_, h)| !h.is_finished());
+        let finished = com_thread_handles
+            .iter()
+            .filter(|(_, h)| h.is_finished())
+            .map(|(port, _)| port.clone())
+            .collect::<Vec<_>>();
+        for port in finished {
+            match com_thread_handles.remove(&port).map(|h| h.join()) {
+                Some(Ok(Err(e))) => {
+                    error!("串口 {} 已停用, 原因: {}", port, e);
+                    failed_ports.insert(port);
+                }
+                _ => info!("串口 {} 的处理线程已退出, 等待重新创建", port),
+            }
+        }
 
         let ports = match serialport::available_ports() {
             Ok(ports) => ports,
@@ -60,8 +78,9 @@ pub fn loop_query(
         };
 
         for p in ports.iter() {
-            if !com_thread_handles.is_empty() {
-                break;
+            if com_thread_handles.contains_key(&p.port_name) || failed_ports.contains(&p.port_name)
+            {
+                continue;
             }
             if config
                 .port
@@ -75,9 +94,12 @@ pub fn loop_query(
                 exit_required.clone(),
                 p.port_name.clone(),
                 tx.clone(),
-                bus.clone(),
+                registry.clone(),
+                config.clone(),
             ) {
-                Ok(h) => com_thread_handles.push((p.port_name.clone(), h)),
+                Ok(h) => {
+                    com_thread_handles.insert(p.port_name.clone(), h);
+                }
                 Err(e) => error!("无法创建串口处理线程: {}", e),
             }
         }
@@ -0,0 +1,424 @@
+use std::{
+    io::Read,
+    net::ToSocketAddrs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+use crate::{
+    app_data::HttpConfig,
+    hardware::com_process::{
+        network::NetworkSample,
+        registry::SharedRegistry,
+        store::TaskSnapshot,
+        wrench::{WrenchContext, WrenchStatus},
+    },
+    message::{BeepInfo, CancelInfo, CancelTarget, ReleaseInfo, ReorderInfo, RequiredAction},
+    redis::message::TaskRequestMsg,
+    AppConfig,
+};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct WrenchView {
+    port: String,
+    mac: String,
+    serial: String,
+    connect_id: String,
+    status: String,
+    voltage: Option<u16>,
+    battery_percent: Option<u8>,
+    remaining_joints: Option<u32>,
+    charging: bool,
+    hibernated: bool,
+    power_connected: bool,
+    online_time: u64,
+    total_joints: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TasksView {
+    current_task: Option<TaskSnapshot>,
+    pending_task: Vec<TaskSnapshot>,
+    finished_task: Vec<TaskSnapshot>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NetworkView {
+    time: String,
+    collisions: u16,
+    crc_errors: u16,
+    tx_count: u16,
+    rx_wanted_count: u16,
+    rx_unwanted_count: u16,
+    rssi: i8,
+    snr: i8,
+    rscp: i8,
+}
+
+impl From<&NetworkSample> for NetworkView {
+    fn from(sample: &NetworkSample) -> Self {
+        Self {
+            time: sample.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            collisions: sample.collisions,
+            crc_errors: sample.crc_errors,
+            tx_count: sample.tx_count,
+            rx_wanted_count: sample.rx_wanted_count,
+            rx_unwanted_count: sample.rx_unwanted_count,
+            rssi: sample.rssi,
+            snr: sample.snr,
+            rscp: sample.rscp,
+        }
+    }
+}
+
+impl From<&WrenchContext> for WrenchView {
+    fn from(wrench: &WrenchContext) -> Self {
+        Self {
+            port: wrench.port.clone(),
+            mac: format!("{:X}", wrench.mac),
+            serial: format!("{:X}", wrench.serial),
+            connect_id: wrench.connect_id.clone(),
+            status: match wrench.status {
+                WrenchStatus::Connected => "connected",
+                WrenchStatus::Working => "working",
+                WrenchStatus::Disconnected => "disconnected",
+            }
+            .to_string(),
+            voltage: wrench.voltage,
+            battery_percent: wrench.battery_estimate().map(|(percent, _)| percent),
+            remaining_joints: wrench.battery_estimate().map(|(_, joints)| joints),
+            charging: wrench.energy.charging,
+            hibernated: wrench.energy.hibernated,
+            power_connected: wrench.energy.power_connected,
+            online_time: wrench.online_time,
+            total_joints: wrench.total_joints,
+        }
+    }
+}
+
+impl From<&WrenchContext> for TasksView {
+    fn from(wrench: &WrenchContext) -> Self {
+        Self {
+            current_task: wrench.current_task.as_ref().map(TaskSnapshot::from),
+            pending_task: wrench.pending_task.iter().map(TaskSnapshot::from).collect(),
+            finished_task: wrench
+                .finished_task
+                .iter()
+                .map(TaskSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body = serde_json::to_string(body).unwrap_or_default();
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap())
+}
+
+fn error_response(status: u16, msg: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    json_response(status, &serde_json::json!({ "error": msg }))
+}
+
+fn accepted() -> Response<std::io::Cursor<Vec<u8>>> {
+    json_response(202, &serde_json::json!({}))
+}
+
+/// 比较 token 时不因第一个不同的字节提前返回
+fn authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    request
+        .headers()
+        .iter()
+        .filter(|h| h.field.equiv("Authorization"))
+        .any(|h| {
+            let value = h.value.as_str().as_bytes();
+            value.len() == expected.len()
+                && value
+                    .iter()
+                    .zip(expected.as_bytes())
+                    .fold(0, |acc, (a, b)| acc | (a ^ b))
+                    == 0
+        })
+}
+
+/// 读取请求体, 超过长度限制时返回 None
+fn read_body(request: &mut Request, limit: usize) -> anyhow::Result<Option<String>> {
+    if matches!(request.body_length(), Some(len) if len > limit) {
+        return Ok(None);
+    }
+
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_string(&mut body)?;
+    if body.len() > limit {
+        return Ok(None);
+    }
+
+    Ok(Some(body))
+}
+
+fn send_action(tx: &mpsc::Sender<RequiredAction>, action: RequiredAction) -> anyhow::Result<()> {
+    info!("发送消息: {} 到主线程", action);
+    tx.send(action)?;
+    Ok(())
+}
+
+/// 取消结果与 Redis 下发的指令一样通过发布通道返回
+fn cancel(
+    tx: &mpsc::Sender<RequiredAction>,
+    serial: u128,
+    target: CancelTarget,
+) -> anyhow::Result<Response<std::io::Cursor<Vec<u8>>>> {
+    let msg_id = Uuid::new_v4().simple().to_string();
+    send_action(
+        tx,
+        RequiredAction::TaskCancel(CancelInfo {
+            msg_id: msg_id.clone(),
+            wrench_serial: Some(serial),
+            station_ip: None,
+            target,
+            cancelled: 0,
+            status: false,
+            desc: "".to_string(),
+        }),
+    )?;
+    Ok(json_response(202, &serde_json::json!({ "msgId": msg_id })))
+}
+
+fn handle(
+    request: &mut Request,
+    config: &HttpConfig,
+    registry: &SharedRegistry,
+    tx: &mpsc::Sender<RequiredAction>,
+) -> anyhow::Result<Response<std::io::Cursor<Vec<u8>>>> {
+    if let Some(token) = &config.token {
+        if !authorized(request, token) {
+            return Ok(error_response(401, "未授权的请求"));
+        }
+    }
+
+    let method = request.method().clone();
+    let url = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let path = url.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>();
+
+    // 路径中的扳手序列号, 与 Redis 消息一致为十六进制
+    let serial = match path.get(1) {
+        Some(s) if path[0] == "wrenches" => match u128::from_str_radix(s, 16) {
+            Ok(s) => Some(s),
+            Err(_) => return Ok(error_response(400, "序列码格式错误")),
+        },
+        _ => None,
+    };
+
+    let registry = registry
+        .lock()
+        .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+    if let Some(serial) = serial {
+        if !registry.wrenches.contains_key(&serial) {
+            return Ok(error_response(404, "未知的扳手"));
+        }
+    }
+
+    let response = match (method, path.as_slice(), serial) {
+        (Method::Get, ["wrenches"], _) => {
+            let mut wrenches = registry
+                .wrenches
+                .values()
+                .map(WrenchView::from)
+                .collect::<Vec<_>>();
+            wrenches.sort_by(|a, b| a.serial.cmp(&b.serial));
+            json_response(200, &wrenches)
+        }
+        (Method::Get, ["wrenches", _], Some(serial)) => {
+            json_response(200, &WrenchView::from(&registry.wrenches[&serial]))
+        }
+        (Method::Get, ["wrenches", _, "tasks"], Some(serial)) => {
+            json_response(200, &TasksView::from(&registry.wrenches[&serial]))
+        }
+        (Method::Get, ["wrenches", _, "network"], Some(serial)) => {
+            let samples = registry.wrenches[&serial]
+                .network
+                .samples
+                .iter()
+                .map(NetworkView::from)
+                .collect::<Vec<_>>();
+            json_response(200, &samples)
+        }
+        (Method::Delete, ["wrenches", _, "tasks", task_id], Some(serial)) => {
+            let target = CancelTarget::Task(task_id.to_string());
+            drop(registry);
+            cancel(tx, serial, target)?
+        }
+        (Method::Delete, ["wrenches", _, "tasks"], Some(serial)) => {
+            drop(registry);
+            cancel(tx, serial, CancelTarget::All)?
+        }
+        (Method::Put, ["wrenches", _, "tasks", "order"], Some(serial)) => {
+            drop(registry);
+            let body = match read_body(request, config.max_body_bytes)? {
+                Some(body) => body,
+                None => return Ok(error_response(413, "请求体过大")),
+            };
+            let task_ids: Vec<String> = match serde_json::from_str(&body) {
+                Ok(v) => v,
+                Err(e) => return Ok(error_response(400, &format!("错误的 Json 格式: {}", e))),
+            };
+
+            // 调整结果与 Redis 下发的指令一样通过发布通道返回
+            let msg_id = Uuid::new_v4().simple().to_string();
+            send_action(
+                tx,
+                RequiredAction::TaskReorder(ReorderInfo {
+                    msg_id: msg_id.clone(),
+                    wrench_serial: serial,
+                    task_ids,
+                    ..Default::default()
+                }),
+            )?;
+            json_response(202, &serde_json::json!({ "msgId": msg_id }))
+        }
+        (Method::Post, ["wrenches", _, "tasks", task_id, "release"], Some(serial)) => {
+            let task_id = task_id.to_string();
+            drop(registry);
+            // 解除结果与 Redis 下发的指令一样通过发布通道返回
+            let msg_id = Uuid::new_v4().simple().to_string();
+            send_action(
+                tx,
+                RequiredAction::TaskRelease(ReleaseInfo {
+                    msg_id: msg_id.clone(),
+                    wrench_serial: serial,
+                    task_id,
+                    ..Default::default()
+                }),
+            )?;
+            json_response(202, &serde_json::json!({ "msgId": msg_id }))
+        }
+        (Method::Post, ["wrenches", _, "beep"], Some(serial)) => {
+            if !registry.is_reachable(serial) {
+                return Ok(error_response(409, "扳手当前没有网关可以到达"));
+            }
+            drop(registry);
+            // 蜂鸣结果与 Redis 下发的指令一样通过发布通道返回
+            let msg_id = Uuid::new_v4().simple().to_string();
+            send_action(
+                tx,
+                RequiredAction::Beep(BeepInfo {
+                    msg_id: msg_id.clone(),
+                    wrench_serial: serial,
+                    ..Default::default()
+                }),
+            )?;
+            json_response(202, &serde_json::json!({ "msgId": msg_id }))
+        }
+        (Method::Post, ["wrenches", _, "clear"], Some(serial)) => {
+            if !registry.is_reachable(serial) {
+                return Ok(error_response(409, "扳手当前没有网关可以到达"));
+            }
+            drop(registry);
+            send_action(tx, RequiredAction::ClearJoints(serial))?;
+            accepted()
+        }
+        (Method::Post, ["tasks"], _) => {
+            drop(registry);
+            let body = match read_body(request, config.max_body_bytes)? {
+                Some(body) => body,
+                None => return Ok(error_response(413, "请求体过大")),
+            };
+            let tasks: Vec<TaskRequestMsg> = match serde_json::from_str(&body) {
+                Ok(v) => v,
+                Err(e) => return Ok(error_response(400, &format!("错误的 Json 格式: {}", e))),
+            };
+
+            // 应答与 Redis 下发的任务一样通过发布通道返回
+            let msg_id = Uuid::new_v4().simple().to_string();
+            send_action(tx, RequiredAction::SendTask((msg_id.clone(), tasks)))?;
+            json_response(202, &serde_json::json!({ "msgId": msg_id }))
+        }
+        _ => error_response(404, "未知的接口"),
+    };
+
+    Ok(response)
+}
+
+pub fn serve(
+    exit_required: Arc<AtomicBool>,
+    config: &AppConfig,
+    registry: SharedRegistry,
+    tx: mpsc::Sender<RequiredAction>,
+) {
+    let http = match &config.http {
+        Some(http) => http,
+        None => return,
+    };
+    let listen = http.listen.clone();
+
+    // 没有 token 时只允许本机访问, 避免任何人都可以下发或取消任务
+    if http.token.is_none() {
+        match listen
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+        {
+            Ok(addrs) if addrs.iter().all(|addr| addr.ip().is_loopback()) => {}
+            Ok(_) => {
+                error!("HTTP 服务监听非回环地址 {} 时必须配置 token", listen);
+                return;
+            }
+            Err(e) => {
+                error!("无法解析 HTTP 监听地址 {}, 原因: {}", listen, e);
+                return;
+            }
+        }
+    }
+
+    let server = match Server::http(&listen) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("无法在 {} 上启动 HTTP 服务, 原因: {}", listen, e);
+            return;
+        }
+    };
+    info!("已在 {} 上启动 HTTP 服务", listen);
+
+    while !exit_required.load(Ordering::Acquire) {
+        let mut request = match server.recv_timeout(Duration::from_secs(1)) {
+            Ok(Some(r)) => r,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("HTTP 服务出现错误: {}", e);
+                continue;
+            }
+        };
+
+        debug!("收到 HTTP 请求: {} {}", request.method(), request.url());
+        let response = match handle(&mut request, http, &registry, &tx) {
+            Ok(r) => r,
+            Err(e) => {
+                error!("处理 HTTP 请求失败: {}", e);
+                error_response(500, &e.to_string())
+            }
+        };
+        if let Err(e) = request.respond(response) {
+            error!("无法应答 HTTP 请求: {}", e);
+        }
+    }
+}
@@ -1,18 +1,19 @@
 mod app_data;
 mod hardware;
+mod http;
 mod message;
 mod redis;
+mod transport;
 
 use std::{
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc, Mutex,
+        mpsc, Arc,
     },
 };
 
 use app_data::Cli;
-use bus::Bus;
 use clap::Parser;
 
 use time::{macros::format_description, UtcOffset};
@@ -20,7 +21,13 @@ use tracing::{debug, error, info, metadata::LevelFilter, span, Level};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt::time::OffsetTime, prelude::*, EnvFilter};
 
-use crate::app_data::AppConfig;
+use crate::{
+    app_data::AppConfig,
+    hardware::com_process::{
+        registry::{dispatch, SharedRegistry},
+        store::StateStore,
+    },
+};
 
 fn run() -> anyhow::Result<()> {
     let cli = Cli::parse();
@@ -50,57 +57,101 @@ fn run() -> anyhow::Result<()> {
     };
 
     let (redis_reader_tx, redis_reader_rx) = mpsc::channel();
+    let (dispatched_tx, dispatched_rx) = mpsc::channel();
+    let (http_tx, http_rx) = mpsc::channel();
     let (redis_writer_tx, redis_writer_rx) = mpsc::channel();
     let (port_handler_tx, port_handler_rx) = mpsc::channel();
-    let bus = Arc::new(Mutex::new(Bus::new(100)));
+    let registry: SharedRegistry = Default::default();
+
+    let mut store = {
+        let state_file = config
+            .state_file
+            .clone()
+            .unwrap_or_else(|| get_exe_path().join("wrench_state.json"));
+        debug!("使用状态文件: {}", state_file.display());
+        StateStore::new(state_file)
+    };
+    store.load(&registry, &config.wrench)?;
 
+    let http_server = config.http.is_some().then(|| {
+        let exit_required = exit_required.clone();
+        let config = config.clone();
+        let registry = registry.clone();
+        let tx = http_tx.clone();
+        std::thread::spawn(move || {
+            span!(Level::ERROR, "HTTP线程").in_scope(|| {
+                info!("启动 HTTP 线程");
+                http::serve(exit_required, &config, registry, tx);
+            });
+        })
+    });
     let redis_reader = {
         let exit_required = exit_required.clone();
         let config = config.clone();
         std::thread::spawn(move || {
             span!(Level::ERROR, "订阅线程").in_scope(|| {
                 info!("启动 Redis 订阅线程");
-                redis::reader::read_redis(exit_required, &config, redis_reader_tx);
+                redis::reader::read_redis(exit_required, &config, redis_reader_tx, dispatched_rx);
             });
         })
     };
     let redis_writer = {
         let exit_required = exit_required.clone();
         let config = config.clone();
+        let outbox_file = config
+            .outbox
+            .path
+            .clone()
+            .unwrap_or_else(|| get_exe_path().join("wrench_outbox.jsonl"));
+        debug!("使用发件箱文件: {}", outbox_file.display());
         std::thread::spawn(move || {
             span!(Level::ERROR, "发布线程").in_scope(|| {
                 info!("启动 Redis 发布线程");
-                redis::writer::write_redis(exit_required, &config, redis_writer_rx);
+                redis::writer::write_redis(exit_required, &config, outbox_file, redis_writer_rx);
             });
         })
     };
     let port_handler = {
         let exit_required = exit_required.clone();
-        let bus = bus.clone();
+        let registry = registry.clone();
         std::thread::spawn(move || {
             span!(Level::ERROR, "串口线程").in_scope(|| {
                 info!("启动串口线程");
-                hardware::port::loop_query(exit_required, port_handler_tx, bus, config);
+                hardware::port::loop_query(exit_required, port_handler_tx, registry, config);
             });
         })
     };
 
     while !exit_required.load(Ordering::Acquire) {
         if let Ok(act) = redis_reader_rx.try_recv() {
-            if let Ok(mut lock) = bus.lock() {
-                debug!("将来自 Redis 的消息 {:?} 广播到所有串口处理线程", act);
-                lock.broadcast(act);
+            let result = dispatch(&registry, act, &redis_writer_tx);
+            if let Err(e) = &result {
+                error!("分发消息失败: {}", e);
+            }
+            // 订阅线程在分发完成后才确认上游消息
+            dispatched_tx.send(result.is_ok()).ok();
+        }
+        if let Ok(act) = http_rx.try_recv() {
+            if let Err(e) = dispatch(&registry, act, &redis_writer_tx) {
+                error!("分发消息失败: {}", e);
             }
         }
         if let Ok(msg) = port_handler_rx.try_recv() {
             debug!("将串口处理线程的消息 {:?} 转发到 Redis", msg);
             redis_writer_tx.send(msg)?;
         }
+        if let Err(e) = store.update(&registry) {
+            error!("保存扳手状态失败: {}", e);
+        }
     }
 
     redis_reader.join().ok();
     redis_writer.join().ok();
     port_handler.join().ok();
+    if let Some(http_server) = http_server {
+        http_server.join().ok();
+    }
+    store.save(&registry)?;
 
     Ok(())
 }
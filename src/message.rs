@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use chrono::{DateTime, Local};
 
-use crate::redis::message::TaskRequestMsg;
+use crate::{app_data::ReworkAction, redis::message::TaskRequestMsg};
 
 #[derive(Debug, Clone, Default)]
 pub struct ConnectInfo {
@@ -25,12 +25,64 @@ pub struct TaskInfo {
     pub status: bool,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct BeepInfo {
+    pub msg_id: String,
+    pub wrench_serial: u128,
+    pub status: bool,
+}
+
+/// 需要取消的任务范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelTarget {
+    Task(String),
+    Detail(String),
+    All,
+}
+
+/// 取消请求和应答, 未指定扳手时取消工位上所有扳手的任务
+#[derive(Debug, Clone)]
+pub struct CancelInfo {
+    pub msg_id: String,
+    pub wrench_serial: Option<u128>,
+    pub station_ip: Option<String>,
+    pub target: CancelTarget,
+    pub cancelled: usize,
+    pub status: bool,
+    pub desc: String,
+}
+
+/// 调整等待队列的请求和应答, 应答中的 task_ids 为调整后的队列顺序
+#[derive(Debug, Clone, Default)]
+pub struct ReorderInfo {
+    pub msg_id: String,
+    pub wrench_serial: u128,
+    pub task_ids: Vec<String>,
+    pub status: bool,
+    pub desc: String,
+}
+
+/// 解除任务锁定的请求和应答
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseInfo {
+    pub msg_id: String,
+    pub wrench_serial: u128,
+    pub task_id: String,
+    pub status: bool,
+    pub desc: String,
+}
+
 #[derive(Debug, Clone)]
 pub enum RequiredAction {
     BindWrench(WrenchInfo),
     CheckConnect(ConnectInfo),
     SendTask((String, Vec<TaskRequestMsg>)),
-    TaskCancel((String, String)),
+    TaskCancel(CancelInfo),
+    /// 主管解除因不合格次数过多而锁定的任务
+    TaskRelease(ReleaseInfo),
+    TaskReorder(ReorderInfo),
+    Beep(BeepInfo),
+    ClearJoints(u128),
 }
 
 impl Display for RequiredAction {
@@ -40,6 +92,10 @@ impl Display for RequiredAction {
             RequiredAction::CheckConnect(_) => write!(f, "RequiredAction::CheckConnect"),
             RequiredAction::SendTask(_) => write!(f, "RequiredAction::SendTask"),
             RequiredAction::TaskCancel(_) => write!(f, "RequiredAction::TaskCancel"),
+            RequiredAction::TaskRelease(_) => write!(f, "RequiredAction::TaskRelease"),
+            RequiredAction::TaskReorder(_) => write!(f, "RequiredAction::TaskReorder"),
+            RequiredAction::Beep(_) => write!(f, "RequiredAction::Beep"),
+            RequiredAction::ClearJoints(_) => write!(f, "RequiredAction::ClearJoints"),
         }
     }
 }
@@ -51,9 +107,16 @@ pub struct FinishedInfo {
     pub task_id: String,
     pub task_detail_id: String,
     pub task_sub_id: String,
+    /// 螺栓序号和该螺栓的第几次拧紧, 均从 1 开始
+    pub bolt: usize,
+    pub repeat: usize,
+    pub finished_bolts: usize,
+    pub bolt_num: usize,
     pub torque: String,
     pub angle: String,
     pub status: bool,
+    /// 不合格时违反的限制
+    pub desc: String,
     pub start_date: DateTime<Local>,
     pub end_date: DateTime<Local>,
 }
@@ -64,6 +127,97 @@ pub struct BasicInfo {
     pub voltage: u32,
     pub storage: u32,
     pub use_time: u64,
+    pub battery_percent: Option<u8>,
+    pub remaining_joints: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GatewayInfo {
+    pub port: String,
+    pub rssi: i8,
+    pub snr: i8,
+    pub rscp: i8,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkInfo {
+    pub wrench_serial: u128,
+    pub collisions: u16,
+    pub crc_errors: u16,
+    pub tx_count: u16,
+    pub rx_wanted_count: u16,
+    pub rx_unwanted_count: u16,
+    pub rssi: i8,
+    pub snr: i8,
+    pub rscp: i8,
+}
+
+/// 通过 MiscInfo 上报的告警, level 为 0 提示, 1 警告, 2 严重
+#[derive(Debug, Clone)]
+pub struct AlarmInfo {
+    pub wrench_serial: u128,
+    pub title: String,
+    pub code: String,
+    pub level: u8,
+    pub desc: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct NokLimitInfo {
+    pub msg_id: String,
+    pub wrench_serial: u128,
+    pub task_id: String,
+    pub task_detail_id: String,
+    pub bolt: usize,
+    pub nok_count: u16,
+    pub action: ReworkAction,
+}
+
+/// 任务在队列中的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStage {
+    Queued,
+    Started,
+    Sent,
+    Confirmed,
+    Completed,
+    Cancelled,
+    Aborted,
+    /// 被更高优先级的任务抢占, 已收取的结果保留到恢复执行
+    Preempted,
+    /// 扳手拒绝或在重发次数耗尽后仍未确认任务
+    SendFailed,
+}
+
+impl TaskStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStage::Queued => "queued",
+            TaskStage::Started => "started",
+            TaskStage::Sent => "sent",
+            TaskStage::Confirmed => "confirmed",
+            TaskStage::Completed => "completed",
+            TaskStage::Cancelled => "cancelled",
+            TaskStage::Aborted => "aborted",
+            TaskStage::Preempted => "preempted",
+            TaskStage::SendFailed => "send_failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LifecycleInfo {
+    pub msg_id: String,
+    pub wrench_serial: u128,
+    pub task_id: String,
+    pub task_detail_id: String,
+    pub stage: TaskStage,
+    pub finished_bolts: usize,
+    pub bolt_num: usize,
+    pub passed_count: usize,
+    pub joint_count: usize,
+    /// 事件发生后仍在等待的任务数量
+    pub pending_count: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -74,6 +228,15 @@ pub enum ResponseAction {
     TaskFinished(FinishedInfo),
     ConnectionTimeout(u128),
     BasicStatus(BasicInfo),
+    GatewayStatus(GatewayInfo),
+    BeepStatus(BeepInfo),
+    CancelStatus(CancelInfo),
+    ReorderStatus(ReorderInfo),
+    ReleaseStatus(ReleaseInfo),
+    NetworkStatus(NetworkInfo),
+    Alarm(AlarmInfo),
+    NokLimit(NokLimitInfo),
+    TaskLifecycle(LifecycleInfo),
 }
 
 impl Display for ResponseAction {
@@ -85,6 +248,15 @@ impl Display for ResponseAction {
             ResponseAction::TaskFinished(_) => write!(f, "ResponseAction::TaskFinished"),
             ResponseAction::ConnectionTimeout(_) => write!(f, "ResponseAction::ConnectionTimeout"),
             ResponseAction::BasicStatus(_) => write!(f, "ResponseAction::BasicStatus"),
+            ResponseAction::GatewayStatus(_) => write!(f, "ResponseAction::GatewayStatus"),
+            ResponseAction::BeepStatus(_) => write!(f, "ResponseAction::BeepStatus"),
+            ResponseAction::CancelStatus(_) => write!(f, "ResponseAction::CancelStatus"),
+            ResponseAction::ReorderStatus(_) => write!(f, "ResponseAction::ReorderStatus"),
+            ResponseAction::ReleaseStatus(_) => write!(f, "ResponseAction::ReleaseStatus"),
+            ResponseAction::NetworkStatus(_) => write!(f, "ResponseAction::NetworkStatus"),
+            ResponseAction::Alarm(_) => write!(f, "ResponseAction::Alarm"),
+            ResponseAction::NokLimit(_) => write!(f, "ResponseAction::NokLimit"),
+            ResponseAction::TaskLifecycle(_) => write!(f, "ResponseAction::TaskLifecycle"),
         }
     }
 }
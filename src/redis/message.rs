@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::app_data::ReworkAction;
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct BindRequestMsg {
@@ -82,7 +84,11 @@ pub struct TaskRequestMsg {
     pub work_mode: String,
     pub bolt_num: String,
     pub repeat_count: String,
+    /// 单一模式中监控量 (扭矩模式为角度, 角度模式为扭矩) 的上限, 为空时不限制
+    #[serde(default)]
     pub target: String,
+    /// 单一模式中监控量的下限, 为空时不限制
+    #[serde(default)]
     pub monitor: String,
     pub torque: String,
     pub torque_deviation_up: String,
@@ -92,6 +98,11 @@ pub struct TaskRequestMsg {
     pub angle_deviation_up: String,
     pub angle_deviation_down: String,
     pub unit: String,
+    /// 每个螺栓累计不合格的最大次数和达到上限后的处理, 缺省时使用配置
+    pub max_nok_count: Option<String>,
+    pub nok_action: Option<ReworkAction>,
+    /// 数值越大越优先, 高于当前任务时抢占当前任务, 缺省为 0
+    pub priority: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -128,6 +139,10 @@ pub struct TaskStatusMsg {
     pub task_id: String,
     pub task_detail_id: String,
     pub task_sub_id: String,
+    pub bolt_no: String,
+    pub repeat_no: String,
+    pub finished_bolts: String,
+    pub bolt_num: String,
     pub wrench_serial: String,
     pub torque: String,
     pub angle: String,
@@ -136,6 +151,7 @@ pub struct TaskStatusMsg {
     pub desc: String,
     pub start_date: String,
     pub end_date: String,
+    pub tighten_time: String,
     pub work_time: String,
 }
 
@@ -151,8 +167,14 @@ pub struct TaskStatus {
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct TaskCancelMsg {
+    /// taskId 和 taskDetailId 都为空时取消扳手的所有任务
+    #[serde(default)]
     pub task_id: String,
+    pub task_detail_id: Option<String>,
+    /// 为空时取消 stationIp 对应工位上所有扳手的任务
+    #[serde(default)]
     pub wrench_serial: String,
+    pub station_ip: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -164,6 +186,121 @@ pub struct TaskCancel {
     pub msg_txt: TaskCancelMsg,
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCancelResponseMsg {
+    pub msg_id: String,
+    pub wrench_serial: String,
+    pub task_id: String,
+    pub task_detail_id: String,
+    pub cancel_count: String,
+    pub status: String,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCancelResponse {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskCancelResponseMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReorderMsg {
+    pub wrench_serial: String,
+    /// 移动到队列最前面的任务, 按给出的顺序排列
+    pub task_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReorder {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskReorderMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReorderResponseMsg {
+    pub msg_id: String,
+    pub wrench_serial: String,
+    pub task_ids: Vec<String>,
+    pub status: String,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReorderResponse {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskReorderResponseMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReleaseMsg {
+    pub task_id: String,
+    pub wrench_serial: String,
+    pub user_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRelease {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskReleaseMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReleaseResponseMsg {
+    pub msg_id: String,
+    pub wrench_serial: String,
+    pub task_id: String,
+    pub status: String,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskReleaseResponse {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskReleaseResponseMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NokLimitMsg {
+    pub msg_id: String,
+    pub task_id: String,
+    pub task_detail_id: String,
+    pub wrench_serial: String,
+    pub bolt_no: String,
+    pub nok_count: String,
+    pub action: String,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NokLimit {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: NokLimitMsg,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct MiscInfoMsg {
@@ -177,6 +314,8 @@ pub struct MiscInfoMsg {
     pub use_time: Option<String>,
     pub storage_num: Option<String>,
     pub voltage: Option<String>,
+    pub battery_percent: Option<String>,
+    pub remaining_joints: Option<String>,
     pub status: Option<String>,
     pub desc: Option<String>,
     #[serde(rename = "type")]
@@ -191,3 +330,103 @@ pub struct MiscInfo {
     pub current_time: String,
     pub msg_txt: MiscInfoMsg,
 }
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayStatusMsg {
+    pub port: String,
+    pub rssi: String,
+    pub snr: String,
+    pub rscp: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayStatus {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: GatewayStatusMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStatusMsg {
+    pub wrench_serial: String,
+    pub collisions: String,
+    pub crc_errors: String,
+    pub tx_count: String,
+    pub rx_wanted_count: String,
+    pub rx_unwanted_count: String,
+    pub rssi: String,
+    pub snr: String,
+    pub rscp: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStatus {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: NetworkStatusMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BeepRequestMsg {
+    pub station_ip: Option<String>,
+    pub wrench_serial: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BeepRequest {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: BeepRequestMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BeepResponseMsg {
+    pub wrench_serial: String,
+    pub status: String,
+    pub desc: String,
+    pub msg_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BeepResponse {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: BeepResponseMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLifecycleMsg {
+    pub msg_id: String,
+    pub task_id: String,
+    pub task_detail_id: String,
+    pub wrench_serial: String,
+    pub stage: String,
+    pub finished_bolts: String,
+    pub bolt_num: String,
+    pub passed_count: String,
+    pub joint_count: String,
+    pub pending_count: String,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLifecycle {
+    pub msg_id: String,
+    pub handler_name: String,
+    pub current_time: String,
+    pub msg_txt: TaskLifecycleMsg,
+}
@@ -1,3 +1,4 @@
 pub mod message;
+pub mod outbox;
 pub mod reader;
 pub mod writer;
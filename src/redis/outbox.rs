@@ -0,0 +1,205 @@
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+/// 已确认的消息少于该数量时不整理文件
+const COMPACT_MIN: u64 = 64;
+
+/// 以 JSON Lines 格式保存在磁盘上的待发布消息队列
+///
+/// 发布成功后只在 .ack 文件中记录已确认消息的序号, 已确认的消息数量
+/// 超过剩余消息数量时才重写队列文件. 队列文件的首行 `#序号` 为文件中
+/// 第一条消息的序号, 整理文件和记录序号的先后顺序不影响恢复的结果.
+pub struct Outbox {
+    path: PathBuf,
+    ack_path: PathBuf,
+    max_len: usize,
+    queue: VecDeque<String>,
+    /// 队列文件中第一条消息的序号
+    base: u64,
+    /// 队列中第一条消息的序号, 之前的消息都已确认
+    acked: u64,
+}
+
+impl Outbox {
+    pub fn open(path: impl AsRef<Path>, max_len: usize) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let ack_path = path.with_extension("ack");
+
+        let mut base = 0;
+        let mut lines = VecDeque::new();
+        if path.exists() {
+            for line in std::fs::read_to_string(&path)?.lines() {
+                if let Some(header) = line.strip_prefix('#') {
+                    base = header.trim().parse()?;
+                } else if !line.is_empty() {
+                    lines.push_back(line.to_string());
+                }
+            }
+        }
+
+        let acked = match std::fs::read_to_string(&ack_path) {
+            Ok(content) => content.trim().parse().unwrap_or_else(|e| {
+                warn!(
+                    "无法解析发件箱序号 {}: {}, 重新发布全部消息",
+                    ack_path.display(),
+                    e
+                );
+                base
+            }),
+            Err(_) => base,
+        };
+        let acked = acked.clamp(base, base + lines.len() as u64);
+        let queue = lines.split_off((acked - base) as usize);
+
+        if !queue.is_empty() {
+            info!(
+                "发件箱 {} 中有 {} 条未发布的消息",
+                path.display(),
+                queue.len()
+            );
+        }
+
+        let mut outbox = Self {
+            path,
+            ack_path,
+            max_len,
+            queue,
+            base,
+            acked,
+        };
+        outbox.truncate()?;
+
+        Ok(outbox)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn front(&self) -> Option<&String> {
+        self.queue.front()
+    }
+
+    pub fn push(&mut self, msg: String) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", msg)?;
+        self.queue.push_back(msg);
+
+        self.truncate()
+    }
+
+    /// 在消息成功发布后将其移出发件箱
+    pub fn pop(&mut self) -> anyhow::Result<()> {
+        if self.queue.pop_front().is_some() {
+            self.acked += 1;
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// 超出容量时丢弃最旧的消息
+    fn truncate(&mut self) -> anyhow::Result<()> {
+        let mut dropped = 0;
+        while self.queue.len() > self.max_len {
+            self.queue.pop_front();
+            dropped += 1;
+        }
+        if dropped > 0 {
+            warn!("发件箱已满, 丢弃了 {} 条最旧的消息", dropped);
+            self.acked += dropped;
+        }
+
+        self.commit()
+    }
+
+    /// 记录已确认的序号, 已确认的消息足够多时整理队列文件
+    fn commit(&mut self) -> anyhow::Result<()> {
+        let dead = self.acked - self.base;
+        if dead == 0 {
+            return Ok(());
+        }
+        std::fs::write(&self.ack_path, self.acked.to_string())?;
+
+        if dead >= COMPACT_MIN.max(self.queue.len() as u64) {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    fn compact(&mut self) -> anyhow::Result<()> {
+        let mut content = format!("#{}\n", self.acked);
+        for msg in self.queue.iter() {
+            content.push_str(msg);
+            content.push('\n');
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.base = self.acked;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Outbox;
+
+    #[test]
+    fn outbox_test() {
+        let path = std::env::temp_dir().join(format!("outbox_test_{}.jsonl", std::process::id()));
+        let ack_path = path.with_extension("ack");
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&ack_path).ok();
+
+        let mut outbox = Outbox::open(&path, 2).unwrap();
+        outbox.push("1".to_string()).unwrap();
+        outbox.push("2".to_string()).unwrap();
+        outbox.push("3".to_string()).unwrap();
+        assert_eq!(outbox.len(), 2);
+
+        let mut outbox = Outbox::open(&path, 2).unwrap();
+        assert_eq!(outbox.front().map(|s| s.as_str()), Some("2"));
+        outbox.pop().unwrap();
+        // 确认消息时不重写队列文件
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n2\n3\n");
+
+        let mut outbox = Outbox::open(&path, 200).unwrap();
+        assert_eq!(outbox.front().map(|s| s.as_str()), Some("3"));
+        assert_eq!(outbox.len(), 1);
+
+        for i in 4..100 {
+            outbox.push(i.to_string()).unwrap();
+        }
+        for _ in 0..90 {
+            outbox.pop().unwrap();
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with('#'));
+        assert!(content.lines().count() < 97);
+
+        let outbox = Outbox::open(&path, 200).unwrap();
+        assert_eq!(outbox.front().map(|s| s.as_str()), Some("93"));
+        assert_eq!(outbox.len(), 7);
+        assert!(!outbox.is_empty());
+
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&ack_path).ok();
+    }
+}
@@ -1,181 +1,299 @@
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc,
+        mpsc::{self, RecvTimeoutError},
     },
     time::Duration,
 };
 
+use anyhow::bail;
 use serde_json::Value;
 use tracing::{debug, error, info};
 
 use super::message::ConnectRequest;
 use crate::{
-    message::{ConnectInfo, RequiredAction, WrenchInfo},
-    redis::message::{BindRequest, TaskCancel, TaskRequest},
+    message::{
+        BeepInfo, CancelInfo, CancelTarget, ConnectInfo, ReleaseInfo, ReorderInfo, RequiredAction,
+        WrenchInfo,
+    },
+    redis::message::{BeepRequest, BindRequest, TaskCancel, TaskRelease, TaskReorder, TaskRequest},
+    transport::{self, Subscriber},
     AppConfig,
 };
 use std::sync::Arc;
 
-fn send_action(tx: &mpsc::Sender<RequiredAction>, action: RequiredAction) -> anyhow::Result<()> {
-    info!("发送消息: {} 到主线程", action);
-    tx.send(action)?;
-    Ok(())
+/// 主线程的接收端和分发结果的通知端
+struct Dispatcher<'a> {
+    exit_required: &'a AtomicBool,
+    tx: &'a mpsc::Sender<RequiredAction>,
+    dispatched: &'a mpsc::Receiver<bool>,
 }
 
-fn main_loop(
-    config: &AppConfig,
-    exit_required: Arc<AtomicBool>,
-    mut con: redis::Connection,
-    tx: &mpsc::Sender<RequiredAction>,
-) -> anyhow::Result<()> {
-    let mut pubsub = con.as_pubsub();
-    pubsub.subscribe(&config.database.reader_queue)?;
-    pubsub.set_read_timeout(Some(std::time::Duration::from_secs(1)))?;
-
-    info!(
-        "已在目标 Redis: {} 上的 {} 队列进行订阅",
-        config.database.reader_uri, config.database.reader_queue
-    );
-    while !exit_required.load(Ordering::Acquire) {
-        let msg = match pubsub.get_message() {
-            Ok(m) => m,
-            Err(e) if e.is_timeout() => {
-                continue;
-            }
+/// 将消息交给主线程, 等待主线程分发完成后返回
+fn send_action(tx: &Dispatcher, action: RequiredAction) -> anyhow::Result<()> {
+    info!("发送消息: {} 到主线程", action);
+    tx.tx.send(action)?;
+    loop {
+        match tx.dispatched.recv_timeout(Duration::from_secs(1)) {
+            Ok(true) => return Ok(()),
+            Ok(false) => bail!("主线程分发消息失败"),
+            Err(RecvTimeoutError::Timeout) if !tx.exit_required.load(Ordering::Acquire) => {}
+            Err(RecvTimeoutError::Timeout) => bail!("程序退出时消息尚未分发"),
             Err(e) => return Err(e.into()),
-        };
-
-        let payload: String = match msg.get_payload() {
-            Ok(p) => p,
-            Err(e) => {
-                error!("错误的 Redis payload, 原因: {}", e);
-                continue;
-            }
-        };
+        }
+    }
+}
 
-        debug!(
-            "从 Redis 通道 {} 接受到内容: {}",
-            msg.get_channel_name(),
-            payload
-        );
-        let parsed: Value = match serde_json::from_str(&payload) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("错误的 Json 格式, 原因: {}", e);
-                continue;
-            }
-        };
+fn process_payload(payload: &str, tx: &Dispatcher) -> anyhow::Result<()> {
+    let parsed: Value = match serde_json::from_str(payload) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("错误的 Json 格式, 原因: {}", e);
+            return Ok(());
+        }
+    };
 
-        match parsed.get("handlerName").map(|v| {
-            info!("接受到来自Redis的 {} 消息", v);
-            v
-        }) {
-            Some(Value::String(s)) if s == "TOPIC_WRENCH_SERIAL_INIT" => {
-                let bind_request: BindRequest = match serde_json::from_str(&payload) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("错误的 Json 格式, 原因: {}", e);
-                        continue;
-                    }
-                };
-                send_action(
-                    tx,
-                    RequiredAction::BindWrench(WrenchInfo {
-                        msg_id: bind_request.msg_id,
-                        connect_id: bind_request.msg_txt.product_serial_no,
-                        ..Default::default()
-                    }),
-                )?;
+    match parsed.get("handlerName").map(|v| {
+        info!("接受到来自Redis的 {} 消息", v);
+        v
+    }) {
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_SERIAL_INIT" => {
+            let bind_request: BindRequest = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            send_action(
+                tx,
+                RequiredAction::BindWrench(WrenchInfo {
+                    msg_id: bind_request.msg_id,
+                    connect_id: bind_request.msg_txt.product_serial_no,
+                    ..Default::default()
+                }),
+            )?;
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_CONNECTION" => {
+            let connect_request: ConnectRequest = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            match u128::from_str_radix(&connect_request.msg_txt.wrench_serial, 16) {
+                Ok(s) => {
+                    send_action(
+                        tx,
+                        RequiredAction::CheckConnect(ConnectInfo {
+                            msg_id: connect_request.msg_id,
+                            wrench_serial: s,
+                            ..Default::default()
+                        }),
+                    )?;
+                }
+                Err(_) => error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数"),
             }
-            Some(Value::String(s)) if s == "TOPIC_WRENCH_CONNECTION" => {
-                let connect_request: ConnectRequest = match serde_json::from_str(&payload) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("错误的 Json 格式, 原因: {}", e);
-                        continue;
-                    }
-                };
-                match u128::from_str_radix(&connect_request.msg_txt.wrench_serial, 16) {
-                    Ok(s) => {
-                        send_action(
-                            tx,
-                            RequiredAction::CheckConnect(ConnectInfo {
-                                msg_id: connect_request.msg_id,
-                                wrench_serial: s,
-                                ..Default::default()
-                            }),
-                        )?;
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_UP_SEND" => {
+            let task_request: TaskRequest = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            send_action(
+                tx,
+                RequiredAction::SendTask((task_request.msg_id, task_request.msg_txt)),
+            )?;
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_CANCEL" => {
+            let task_cancel: TaskCancel = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            let msg_txt = task_cancel.msg_txt;
+            let wrench_serial = if msg_txt.wrench_serial.is_empty() {
+                None
+            } else {
+                match u128::from_str_radix(&msg_txt.wrench_serial, 16) {
+                    Ok(s) => Some(s),
+                    Err(_) => {
+                        error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数");
+                        return Ok(());
                     }
-                    Err(_) => error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数"),
                 }
+            };
+            let station_ip = msg_txt.station_ip.filter(|s| !s.is_empty());
+            if wrench_serial.is_none() && station_ip.is_none() {
+                error!("取消任务时必须指定扳手序列码或工位");
+                return Ok(());
             }
-            Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_UP_SEND" => {
-                let task_request: TaskRequest = match serde_json::from_str(&payload) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("错误的 Json 格式, 原因: {}", e);
-                        continue;
-                    }
-                };
-                send_action(
-                    tx,
-                    RequiredAction::SendTask((task_request.msg_id, task_request.msg_txt)),
-                )?;
+            let target = match msg_txt.task_detail_id.filter(|s| !s.is_empty()) {
+                Some(detail_id) => CancelTarget::Detail(detail_id),
+                None if msg_txt.task_id.is_empty() => CancelTarget::All,
+                None => CancelTarget::Task(msg_txt.task_id),
+            };
+            send_action(
+                tx,
+                RequiredAction::TaskCancel(CancelInfo {
+                    msg_id: task_cancel.msg_id,
+                    wrench_serial,
+                    station_ip,
+                    target,
+                    cancelled: 0,
+                    status: false,
+                    desc: "".to_string(),
+                }),
+            )?;
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_RELEASE" => {
+            let task_release: TaskRelease = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            info!(
+                "用户 {:?} 请求解除任务 {} 的锁定",
+                task_release.msg_txt.user_id, task_release.msg_txt.task_id
+            );
+            match u128::from_str_radix(&task_release.msg_txt.wrench_serial, 16) {
+                Ok(s) => {
+                    send_action(
+                        tx,
+                        RequiredAction::TaskRelease(ReleaseInfo {
+                            msg_id: task_release.msg_id,
+                            wrench_serial: s,
+                            task_id: task_release.msg_txt.task_id,
+                            ..Default::default()
+                        }),
+                    )?;
+                }
+                Err(_) => error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数"),
             }
-            Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_CANCEL" => {
-                let task_cancel: TaskCancel = match serde_json::from_str(&payload) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("错误的 Json 格式, 原因: {}", e);
-                        continue;
-                    }
-                };
-                send_action(
-                    tx,
-                    RequiredAction::TaskCancel((
-                        task_cancel.msg_txt.wrench_serial,
-                        task_cancel.msg_txt.task_id,
-                    )),
-                )?;
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_TASK_REORDER" => {
+            let task_reorder: TaskReorder = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            match u128::from_str_radix(&task_reorder.msg_txt.wrench_serial, 16) {
+                Ok(s) => {
+                    send_action(
+                        tx,
+                        RequiredAction::TaskReorder(ReorderInfo {
+                            msg_id: task_reorder.msg_id,
+                            wrench_serial: s,
+                            task_ids: task_reorder.msg_txt.task_ids,
+                            ..Default::default()
+                        }),
+                    )?;
+                }
+                Err(_) => error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数"),
             }
-            Some(Value::String(s))
-                if s == "TOPIC_WRENCH_SERIAL_INIT_ASK"
-                    || s == "TOPIC_WRENCH_CONNECTION_ASK"
-                    || s == "TOPIC_WRENCH_TASK_UP_ASK"
-                    || s == "TOPIC_WRENCH_WORK_COLLECTION_RECEIVE"
-                    || s == "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE" => {}
-            _ => {
-                error!("未知的消息格式");
+        }
+        Some(Value::String(s)) if s == "TOPIC_WRENCH_BEEP" => {
+            let beep_request: BeepRequest = match serde_json::from_str(payload) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("错误的 Json 格式, 原因: {}", e);
+                    return Ok(());
+                }
+            };
+            match u128::from_str_radix(&beep_request.msg_txt.wrench_serial, 16) {
+                Ok(s) => {
+                    send_action(
+                        tx,
+                        RequiredAction::Beep(BeepInfo {
+                            msg_id: beep_request.msg_id,
+                            wrench_serial: s,
+                            ..Default::default()
+                        }),
+                    )?;
+                }
+                Err(_) => error!("序列码格式错误, 注意序列码必须为一个 128bit 的十六进制数"),
             }
         }
+        Some(Value::String(s))
+            if s == "TOPIC_WRENCH_SERIAL_INIT_ASK"
+                || s == "TOPIC_WRENCH_CONNECTION_ASK"
+                || s == "TOPIC_WRENCH_TASK_UP_ASK"
+                || s == "TOPIC_WRENCH_WORK_COLLECTION_RECEIVE"
+                || s == "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE"
+                || s == "TOPIC_WRENCH_GATEWAY_COLLECTION_RECEIVE"
+                || s == "TOPIC_WRENCH_BEEP_ASK"
+                || s == "TOPIC_WRENCH_TASK_CANCEL_ASK"
+                || s == "TOPIC_WRENCH_TASK_REORDER_ASK"
+                || s == "TOPIC_WRENCH_TASK_RELEASE_ASK"
+                || s == "TOPIC_WRENCH_NETWORK_COLLECTION_RECEIVE"
+                || s == "TOPIC_WRENCH_NOK_LIMIT_RECEIVE"
+                || s == "TOPIC_WRENCH_TASK_LIFECYCLE_RECEIVE" => {}
+        _ => {
+            error!("未知的消息格式");
+        }
     }
 
     Ok(())
 }
 
-fn get_pubsub(config: &AppConfig) -> anyhow::Result<redis::Connection> {
-    let client = redis::Client::open(config.database.reader_uri.clone())?;
-    let con = client.get_connection()?;
+fn main_loop(
+    exit_required: Arc<AtomicBool>,
+    subscriber: &mut dyn Subscriber,
+    tx: &mpsc::Sender<RequiredAction>,
+    dispatched: &mpsc::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let dispatcher = Dispatcher {
+        exit_required: &exit_required,
+        tx,
+        dispatched,
+    };
+    while !exit_required.load(Ordering::Acquire) {
+        let delivery = match subscriber.receive()? {
+            Some(d) => d,
+            None => continue,
+        };
 
-    info!("已连接到 Redis: {}", config.database.reader_uri);
-    Ok(con)
+        debug!("从 {} 接受到内容: {}", delivery.source, delivery.payload);
+        process_payload(&delivery.payload, &dispatcher)?;
+        // 主线程分发完成或确认消息无法处理后才确认, 分发失败时不确认,
+        // 消息留在流的待确认列表中, 重新连接后会再次投递
+        subscriber.ack()?;
+    }
+
+    Ok(())
 }
 
 pub fn read_redis(
     exit_required: Arc<AtomicBool>,
     config: &AppConfig,
     tx: mpsc::Sender<RequiredAction>,
+    dispatched: mpsc::Receiver<bool>,
 ) {
+    let mut subscriber = transport::subscriber(config);
     while !exit_required.load(Ordering::Acquire) {
-        match get_pubsub(config) {
-            Ok(con) => {
-                if let Err(e) = main_loop(config, exit_required.clone(), con, &tx) {
-                    error!("Redis 订阅线程出现错误: {}, 尝试重新获取 Redis 连接", e);
+        match subscriber.connect() {
+            Ok(_) => {
+                if let Err(e) =
+                    main_loop(exit_required.clone(), subscriber.as_mut(), &tx, &dispatched)
+                {
+                    error!("订阅线程出现错误: {}, 尝试重新连接", e);
                 }
             }
             Err(e) => {
-                error!("无法连接到 Redis, 原因: {}, 将在 1 秒后进行重连", e);
+                error!(
+                    "无法连接到 {}, 原因: {}, 将在 1 秒后进行重连",
+                    config.database.reader_uri, e
+                );
                 std::thread::sleep(Duration::from_secs(1));
             }
         }
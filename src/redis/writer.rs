@@ -1,223 +1,438 @@
-use redis::Commands;
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
-use crate::message::ResponseAction;
+use crate::app_data::ReworkAction;
+use crate::message::{CancelTarget, ResponseAction, TaskStage};
 use crate::redis::message::{
-    BindResponse, BindResponseMsg, ConnectResponse, ConnectResponseMsg, MiscInfo, MiscInfoMsg,
-    TaskResponse, TaskResponseMsg, TaskStatus, TaskStatusMsg,
+    BeepResponse, BeepResponseMsg, BindResponse, BindResponseMsg, ConnectResponse,
+    ConnectResponseMsg, GatewayStatus, GatewayStatusMsg, MiscInfo, MiscInfoMsg, NetworkStatus,
+    NetworkStatusMsg, NokLimit, NokLimitMsg, TaskCancelResponse, TaskCancelResponseMsg,
+    TaskLifecycle, TaskLifecycleMsg, TaskReleaseResponse, TaskReleaseResponseMsg,
+    TaskReorderResponse, TaskReorderResponseMsg, TaskResponse, TaskResponseMsg, TaskStatus,
+    TaskStatusMsg,
 };
+use crate::redis::outbox::Outbox;
+use crate::transport::{self, Publisher};
 use crate::AppConfig;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-fn publish_msg<T: serde::Serialize>(
-    con: &mut redis::Connection,
-    queue: &str,
-    msg: T,
-) -> anyhow::Result<()> {
-    let msg = serde_json::to_string(&msg)?;
-    info!("发布消息: {} 到 Redis", msg);
-    con.publish(queue, msg)?;
-    Ok(())
+/// 将主线程发来的消息序列化后写入发件箱
+fn receive_msg(rx: &mpsc::Receiver<ResponseAction>, outbox: &mut Outbox) {
+    let mut next = rx.recv_timeout(Duration::from_millis(10)).ok();
+    while let Some(msg) = next {
+        if cfg!(debug_assertions) {
+            debug!("收到主线程的消息: {:?}", msg);
+        } else {
+            info!("收到主线程的消息: {}", msg);
+        }
+        match build_msg(msg) {
+            Ok(msg) => {
+                if let Err(e) = outbox.push(msg) {
+                    error!("无法将消息写入发件箱: {}", e);
+                }
+            }
+            Err(e) => error!("无法序列化需要发布的消息: {}", e),
+        }
+        next = rx.try_recv().ok();
+    }
+}
+
+fn build_msg(msg: ResponseAction) -> anyhow::Result<String> {
+    let msg = match msg {
+        ResponseAction::BindResponse(info) => {
+            let bind_response = BindResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_SERIAL_INIT_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: BindResponseMsg {
+                    product_serial_no: info.connect_id,
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    msg_id: info.msg_id,
+                },
+            };
+            serde_json::to_string(&bind_response)?
+        }
+        ResponseAction::ConnectStatus(info) => {
+            let connect_response = ConnectResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_CONNECTION_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: ConnectResponseMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: if info.status {
+                        "连接成功"
+                    } else {
+                        "连接失败"
+                    }
+                    .to_string(),
+                    msg_id: info.msg_id,
+                },
+            };
+            serde_json::to_string(&connect_response)?
+        }
+        ResponseAction::TaskStatus(info) => {
+            let task_response = TaskResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_TASK_UP_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskResponseMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: if info.status {
+                        "接受成功"
+                    } else {
+                        "接受失败"
+                    }
+                    .to_string(),
+                    msg_id: info.msg_id,
+                },
+            };
+            serde_json::to_string(&task_response)?
+        }
+        ResponseAction::TaskFinished(info) => {
+            let task_response = TaskStatus {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_WORK_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskStatusMsg {
+                    msg_id: info.msg_id,
+                    task_id: info.task_id,
+                    task_detail_id: info.task_detail_id,
+                    task_sub_id: info.task_sub_id,
+                    bolt_no: info.bolt.to_string(),
+                    repeat_no: info.repeat.to_string(),
+                    finished_bolts: info.finished_bolts.to_string(),
+                    bolt_num: info.bolt_num.to_string(),
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    torque: info.torque,
+                    angle: info.angle,
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    consume_time: (info.end_date - info.start_date).num_seconds().to_string(),
+                    desc: if info.status {
+                        "通过".to_string()
+                    } else if info.desc.is_empty() {
+                        "不通过".to_string()
+                    } else {
+                        format!("不通过: {}", info.desc)
+                    },
+                    start_date: info.start_date.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end_date: info.end_date.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    tighten_time: info.end_date.to_rfc3339(),
+                    work_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                },
+            };
+            serde_json::to_string(&task_response)?
+        }
+        ResponseAction::ConnectionTimeout(info) => {
+            let timeout_response = MiscInfo {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: MiscInfoMsg {
+                    wrench_serial: format!("{:X}", info),
+                    title: None,
+                    code: None,
+                    start_date: None,
+                    end_date: None,
+                    level: None,
+                    consume_time: None,
+                    use_time: None,
+                    storage_num: None,
+                    status: Some("2".to_string()),
+                    voltage: None,
+                    battery_percent: None,
+                    remaining_joints: None,
+                    desc: Some("断开连接".to_string()),
+                    msg_type: "3".to_string(),
+                },
+            };
+            serde_json::to_string(&timeout_response)?
+        }
+        ResponseAction::BasicStatus(info) => {
+            let basic_response = MiscInfo {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: MiscInfoMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    title: None,
+                    code: None,
+                    start_date: None,
+                    end_date: None,
+                    level: None,
+                    consume_time: None,
+                    use_time: Some(format!("{}", info.use_time)),
+                    storage_num: Some(format!("{}", info.storage)),
+                    status: Some("2".to_string()),
+                    voltage: Some(format!("{}", info.voltage)),
+                    battery_percent: info.battery_percent.map(|p| format!("{}", p)),
+                    remaining_joints: info.remaining_joints.map(|j| format!("{}", j)),
+                    desc: Some("扳手基础数据发送".to_string()),
+                    msg_type: "0".to_string(),
+                },
+            };
+            serde_json::to_string(&basic_response)?
+        }
+        ResponseAction::GatewayStatus(info) => {
+            let gateway_response = GatewayStatus {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_GATEWAY_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: GatewayStatusMsg {
+                    port: info.port,
+                    rssi: format!("{}", info.rssi),
+                    snr: format!("{}", info.snr),
+                    rscp: format!("{}", info.rscp),
+                },
+            };
+            serde_json::to_string(&gateway_response)?
+        }
+        ResponseAction::BeepStatus(info) => {
+            let beep_response = BeepResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_BEEP_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: BeepResponseMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: if info.status {
+                        "蜂鸣成功"
+                    } else {
+                        "蜂鸣失败"
+                    }
+                    .to_string(),
+                    msg_id: info.msg_id,
+                },
+            };
+            serde_json::to_string(&beep_response)?
+        }
+        ResponseAction::CancelStatus(info) => {
+            let (task_id, task_detail_id) = match info.target {
+                CancelTarget::Task(task_id) => (task_id, "".to_string()),
+                CancelTarget::Detail(detail_id) => ("".to_string(), detail_id),
+                CancelTarget::All => ("".to_string(), "".to_string()),
+            };
+            let cancel_response = TaskCancelResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_TASK_CANCEL_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskCancelResponseMsg {
+                    msg_id: info.msg_id,
+                    wrench_serial: info
+                        .wrench_serial
+                        .map(|s| format!("{:X}", s))
+                        .unwrap_or_default(),
+                    task_id,
+                    task_detail_id,
+                    cancel_count: info.cancelled.to_string(),
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: info.desc,
+                },
+            };
+            serde_json::to_string(&cancel_response)?
+        }
+        ResponseAction::ReorderStatus(info) => {
+            let reorder_response = TaskReorderResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_TASK_REORDER_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskReorderResponseMsg {
+                    msg_id: info.msg_id,
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    task_ids: info.task_ids,
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: info.desc,
+                },
+            };
+            serde_json::to_string(&reorder_response)?
+        }
+        ResponseAction::ReleaseStatus(info) => {
+            let release_response = TaskReleaseResponse {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_TASK_RELEASE_ASK".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskReleaseResponseMsg {
+                    msg_id: info.msg_id,
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    task_id: info.task_id,
+                    status: if info.status { "0" } else { "1" }.to_string(),
+                    desc: info.desc,
+                },
+            };
+            serde_json::to_string(&release_response)?
+        }
+        ResponseAction::NetworkStatus(info) => {
+            let network_response = NetworkStatus {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_NETWORK_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: NetworkStatusMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    collisions: format!("{}", info.collisions),
+                    crc_errors: format!("{}", info.crc_errors),
+                    tx_count: format!("{}", info.tx_count),
+                    rx_wanted_count: format!("{}", info.rx_wanted_count),
+                    rx_unwanted_count: format!("{}", info.rx_unwanted_count),
+                    rssi: format!("{}", info.rssi),
+                    snr: format!("{}", info.snr),
+                    rscp: format!("{}", info.rscp),
+                },
+            };
+            serde_json::to_string(&network_response)?
+        }
+        ResponseAction::NokLimit(info) => {
+            let nok_response = NokLimit {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_NOK_LIMIT_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: NokLimitMsg {
+                    msg_id: info.msg_id,
+                    task_id: info.task_id,
+                    task_detail_id: info.task_detail_id,
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    bolt_no: info.bolt.to_string(),
+                    nok_count: info.nok_count.to_string(),
+                    action: info.action.as_str().to_string(),
+                    desc: match info.action {
+                        ReworkAction::Lock => "螺栓不合格次数达到上限, 任务已锁定",
+                        ReworkAction::Abort => "螺栓不合格次数达到上限, 任务已中止",
+                        ReworkAction::Release => "螺栓不合格次数达到上限, 等待主管解除锁定",
+                    }
+                    .to_string(),
+                },
+            };
+            serde_json::to_string(&nok_response)?
+        }
+        ResponseAction::TaskLifecycle(info) => {
+            let lifecycle_response = TaskLifecycle {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_TASK_LIFECYCLE_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: TaskLifecycleMsg {
+                    msg_id: info.msg_id,
+                    task_id: info.task_id,
+                    task_detail_id: info.task_detail_id,
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    stage: info.stage.as_str().to_string(),
+                    finished_bolts: info.finished_bolts.to_string(),
+                    bolt_num: info.bolt_num.to_string(),
+                    passed_count: info.passed_count.to_string(),
+                    joint_count: info.joint_count.to_string(),
+                    pending_count: info.pending_count.to_string(),
+                    desc: match info.stage {
+                        TaskStage::Queued => "任务已进入队列",
+                        TaskStage::Started => "任务开始执行",
+                        TaskStage::Sent => "任务已下发到扳手",
+                        TaskStage::Confirmed => "扳手已确认接收任务",
+                        TaskStage::Completed => "任务已完成",
+                        TaskStage::Cancelled => "任务已取消",
+                        TaskStage::Aborted => "任务已中止",
+                        TaskStage::Preempted => "任务被抢占, 等待恢复执行",
+                        TaskStage::SendFailed => "任务下发失败, 扳手未确认",
+                    }
+                    .to_string(),
+                },
+            };
+            serde_json::to_string(&lifecycle_response)?
+        }
+        ResponseAction::Alarm(info) => {
+            let alarm_response = MiscInfo {
+                msg_id: Uuid::new_v4().simple().to_string(),
+                handler_name: "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE".to_string(),
+                current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                msg_txt: MiscInfoMsg {
+                    wrench_serial: format!("{:X}", info.wrench_serial),
+                    title: Some(info.title),
+                    code: Some(info.code),
+                    start_date: Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+                    end_date: None,
+                    level: Some(format!("{}", info.level)),
+                    consume_time: None,
+                    use_time: None,
+                    storage_num: None,
+                    status: Some("2".to_string()),
+                    voltage: None,
+                    battery_percent: None,
+                    remaining_joints: None,
+                    desc: Some(info.desc),
+                    msg_type: "1".to_string(),
+                },
+            };
+            serde_json::to_string(&alarm_response)?
+        }
+    };
+
+    Ok(msg)
 }
 
 fn main_loop(
-    config: &AppConfig,
-    mut con: redis::Connection,
     exit_required: Arc<AtomicBool>,
+    publisher: &mut dyn Publisher,
     rx: &mpsc::Receiver<ResponseAction>,
+    outbox: &mut Outbox,
 ) -> anyhow::Result<()> {
-    info!(
-        "已在目标 Redis: {} 上的 {} 队列进行发布循环, 将在收取主线程的数据之后进行发布",
-        config.database.writer_uri, config.database.writer_queue
-    );
     while !exit_required.load(Ordering::Acquire) {
-        if let Ok(msg) = rx.try_recv() {
-            if cfg!(debug_assertions) {
-                debug!("收到主线程的消息: {:?}", msg);
-            } else {
-                info!("收到主线程的消息: {}", msg);
-            }
-            match msg {
-                ResponseAction::BindResponse(info) => {
-                    let bind_response = BindResponse {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_SERIAL_INIT_ASK".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: BindResponseMsg {
-                            product_serial_no: info.connect_id,
-                            wrench_serial: format!("{:X}", info.wrench_serial),
-                            msg_id: info.msg_id,
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        bind_response,
-                    )?;
-                }
-                ResponseAction::ConnectStatus(info) => {
-                    let connect_response = ConnectResponse {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_CONNECTION_ASK".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: ConnectResponseMsg {
-                            wrench_serial: format!("{:X}", info.wrench_serial),
-                            status: if info.status { "0" } else { "1" }.to_string(),
-                            desc: if info.status {
-                                "连接成功"
-                            } else {
-                                "连接失败"
-                            }
-                            .to_string(),
-                            msg_id: info.msg_id,
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        connect_response,
-                    )?;
-                }
-                ResponseAction::TaskStatus(info) => {
-                    let task_response = TaskResponse {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_TASK_UP_ASK".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: TaskResponseMsg {
-                            wrench_serial: format!("{:X}", info.wrench_serial),
-                            status: if info.status { "0" } else { "1" }.to_string(),
-                            desc: if info.status {
-                                "接受成功"
-                            } else {
-                                "接受失败"
-                            }
-                            .to_string(),
-                            msg_id: info.msg_id,
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        task_response,
-                    )?;
-                }
-                ResponseAction::TaskFinished(info) => {
-                    let task_response = TaskStatus {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_WORK_COLLECTION_RECEIVE".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: TaskStatusMsg {
-                            msg_id: info.msg_id,
-                            task_id: info.task_id,
-                            task_detail_id: info.task_detail_id,
-                            task_sub_id: info.task_sub_id,
-                            wrench_serial: format!("{:X}", info.wrench_serial),
-                            torque: info.torque,
-                            angle: info.angle,
-                            status: if info.status { "0" } else { "1" }.to_string(),
-                            consume_time: (info.end_date - info.start_date)
-                                .num_seconds()
-                                .to_string(),
-                            desc: if info.status { "通过" } else { "不通过" }.to_string(),
-                            start_date: info.start_date.format("%Y-%m-%d %H:%M:%S").to_string(),
-                            end_date: info.end_date.format("%Y-%m-%d %H:%M:%S").to_string(),
-                            work_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        task_response,
-                    )?;
-                }
-                ResponseAction::ConnectionTimeout(info) => {
-                    let timeout_response = MiscInfo {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: MiscInfoMsg {
-                            wrench_serial: format!("{:X}", info),
-                            title: None,
-                            code: None,
-                            start_date: None,
-                            end_date: None,
-                            level: None,
-                            consume_time: None,
-                            use_time: None,
-                            storage_num: None,
-                            status: Some("2".to_string()),
-                            voltage: None,
-                            desc: Some("断开连接".to_string()),
-                            msg_type: "3".to_string(),
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        timeout_response,
-                    )?;
-                }
-                ResponseAction::BasicStatus(info) => {
-                    let basic_response = MiscInfo {
-                        msg_id: Uuid::new_v4().simple().to_string(),
-                        handler_name: "TOPIC_WRENCH_OTHER_COLLECTION_RECEIVE".to_string(),
-                        current_time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        msg_txt: MiscInfoMsg {
-                            wrench_serial: format!("{:X}", info.wrench_serial),
-                            title: None,
-                            code: None,
-                            start_date: None,
-                            end_date: None,
-                            level: None,
-                            consume_time: None,
-                            use_time: Some(format!("{}", info.use_time)),
-                            storage_num: Some(format!("{}", info.storage)),
-                            status: Some("2".to_string()),
-                            voltage: Some(format!("{}", info.voltage)),
-                            desc: Some("扳手基础数据发送".to_string()),
-                            msg_type: "0".to_string(),
-                        },
-                    };
-                    publish_msg(
-                        &mut con,
-                        config.database.writer_queue.as_str(),
-                        basic_response,
-                    )?;
-                }
-            }
+        receive_msg(rx, outbox);
+
+        // 按顺序发布, 只有发布成功的消息才会从发件箱中移除
+        while let Some(msg) = outbox.front() {
+            info!("发布消息: {}", msg);
+            publisher.publish(msg)?;
+            outbox.pop()?;
         }
+        publisher.keep_alive()?;
     }
 
     Ok(())
 }
 
-fn get_con(config: &AppConfig) -> anyhow::Result<redis::Connection> {
-    let client = redis::Client::open(config.database.writer_uri.clone())?;
-    let con = client.get_connection()?;
-
-    info!("已连接到 Redis: {}", config.database.writer_uri);
-    Ok(con)
-}
-
 pub fn write_redis(
     exit_required: Arc<AtomicBool>,
     config: &AppConfig,
+    outbox_path: PathBuf,
     rx: mpsc::Receiver<ResponseAction>,
 ) {
+    let mut outbox = match Outbox::open(&outbox_path, config.outbox.max_messages) {
+        Ok(outbox) => outbox,
+        Err(e) => {
+            error!("无法打开发件箱 {}, 原因: {}", outbox_path.display(), e);
+            return;
+        }
+    };
+
+    let mut publisher = transport::publisher(config);
     while !exit_required.load(Ordering::Acquire) {
-        match get_con(config) {
-            Ok(con) => {
-                if let Err(e) = main_loop(config, con, exit_required.clone(), &rx) {
-                    error!("Redis 发布线程出现错误: {}, 尝试重新获取 Redis 连接", e);
+        match publisher.connect() {
+            Ok(_) => {
+                if let Err(e) =
+                    main_loop(exit_required.clone(), publisher.as_mut(), &rx, &mut outbox)
+                {
+                    error!("发布线程出现错误: {}, 尝试重新连接", e);
                 }
             }
             Err(e) => {
-                error!("无法连接到 Redis, 原因: {}, 将在 1 秒后进行重连", e);
-                std::thread::sleep(Duration::from_secs(1));
+                error!(
+                    "无法连接到 {}, 原因: {}, 将在 1 秒后进行重连",
+                    config.database.writer_uri, e
+                );
+                // 断开期间继续收取消息, 避免丢失
+                let retry_at = Instant::now() + Duration::from_secs(1);
+                while Instant::now() < retry_at && !exit_required.load(Ordering::Acquire) {
+                    receive_msg(&rx, &mut outbox);
+                }
             }
         }
     }
+
+    receive_msg(&rx, &mut outbox);
+    if !outbox.is_empty() {
+        info!(
+            "发件箱中仍有 {} 条消息未发布, 将在下次启动后重发",
+            outbox.len()
+        );
+    }
 }
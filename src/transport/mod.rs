@@ -0,0 +1,67 @@
+mod mqtt;
+mod redis;
+
+use crate::{app_data::TransportKind, AppConfig};
+
+use self::{
+    mqtt::{MqttPublisher, MqttSubscriber},
+    redis::{RedisPublisher, RedisSubscriber},
+};
+
+/// 从上游收取的一条 JSON 消息
+pub struct Delivery {
+    pub source: String,
+    pub payload: String,
+}
+
+/// 上游请求的接收端
+pub trait Subscriber: Send {
+    fn connect(&mut self) -> anyhow::Result<()>;
+
+    /// 等待下一条消息, 超时返回 None
+    fn receive(&mut self) -> anyhow::Result<Option<Delivery>>;
+
+    /// 确认上一条收到的消息已经处理完成
+    fn ack(&mut self) -> anyhow::Result<()>;
+}
+
+/// 上游响应的发布端
+pub trait Publisher: Send {
+    fn connect(&mut self) -> anyhow::Result<()>;
+
+    /// 发布一条消息, 返回成功时消息已经被上游接收
+    fn publish(&mut self, msg: &str) -> anyhow::Result<()>;
+
+    /// 没有消息需要发布时维持连接
+    fn keep_alive(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn subscriber(config: &AppConfig) -> Box<dyn Subscriber> {
+    match config.transport {
+        TransportKind::Redis => Box::new(RedisSubscriber::new(
+            &config.database.reader_uri,
+            &config.database.reader_queue,
+            config.database.stream.clone(),
+        )),
+        TransportKind::Mqtt => Box::new(MqttSubscriber::new(
+            &config.database.reader_uri,
+            &config.database.reader_queue,
+        )),
+    }
+}
+
+pub fn publisher(config: &AppConfig) -> Box<dyn Publisher> {
+    match config.transport {
+        TransportKind::Redis => Box::new(RedisPublisher::new(
+            &config.database.writer_uri,
+            &config.database.writer_queue,
+            config.database.stream.clone(),
+        )),
+        TransportKind::Mqtt => Box::new(MqttPublisher::new(
+            &config.database.writer_uri,
+            &config.database.writer_queue,
+        )),
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::VecDeque, time::Duration};
+
+use redis::{
+    streams::{StreamMaxlen, StreamReadOptions, StreamReadReply},
+    Commands,
+};
+use tracing::{error, info};
+
+use crate::app_data::StreamConfig;
+
+use super::{Delivery, Publisher, Subscriber};
+
+fn connect(uri: &str) -> anyhow::Result<redis::Connection> {
+    let client = redis::Client::open(uri)?;
+    let con = client.get_connection()?;
+
+    info!("已连接到 Redis: {}", uri);
+    Ok(con)
+}
+
+pub struct RedisSubscriber {
+    uri: String,
+    queue: String,
+    stream: Option<StreamConfig>,
+    con: Option<redis::Connection>,
+    // 先处理上次已读取但未确认的消息, 之后再读取新的消息
+    pending: bool,
+    received: VecDeque<(String, String)>,
+    last_id: Option<String>,
+}
+
+impl RedisSubscriber {
+    pub fn new(uri: &str, queue: &str, stream: Option<StreamConfig>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            queue: queue.to_string(),
+            stream,
+            con: None,
+            pending: true,
+            received: VecDeque::new(),
+            last_id: None,
+        }
+    }
+
+    fn connection(&mut self) -> anyhow::Result<&mut redis::Connection> {
+        self.con
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("尚未连接到 Redis"))
+    }
+
+    fn receive_pubsub(&mut self) -> anyhow::Result<Option<Delivery>> {
+        let value = match self.connection()?.recv_response() {
+            Ok(v) => v,
+            Err(e) if e.is_timeout() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // 订阅确认等非消息内容直接忽略
+        let msg = match redis::Msg::from_value(&value) {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+        match msg.get_payload() {
+            Ok(payload) => Ok(Some(Delivery {
+                source: msg.get_channel_name().to_string(),
+                payload,
+            })),
+            Err(e) => {
+                error!("错误的 Redis payload, 原因: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn receive_stream(&mut self, stream: &StreamConfig) -> anyhow::Result<Option<Delivery>> {
+        if self.received.is_empty() {
+            let opts = StreamReadOptions::default()
+                .group(&stream.group, &stream.consumer)
+                .count(10)
+                .block(1000);
+            let id = if self.pending { "0" } else { ">" };
+            let queue = self.queue.clone();
+            let reply: Option<StreamReadReply> =
+                self.connection()?
+                    .xread_options(&[queue.as_str()], &[id], &opts)?;
+
+            for entry in reply
+                .map(|r| r.keys.into_iter().flat_map(|k| k.ids).collect::<Vec<_>>())
+                .unwrap_or_default()
+            {
+                match entry.map.get(&stream.field) {
+                    Some(v) => match redis::from_redis_value::<String>(v) {
+                        Ok(payload) => self.received.push_back((entry.id, payload)),
+                        Err(e) => {
+                            error!("错误的 Redis payload, 原因: {}", e);
+                            self.received.push_back((entry.id, String::new()));
+                        }
+                    },
+                    None => {
+                        error!("流消息 {} 中没有 {} 字段", entry.id, stream.field);
+                        self.received.push_back((entry.id, String::new()));
+                    }
+                }
+            }
+
+            if self.pending && self.received.is_empty() {
+                self.pending = false;
+            }
+        }
+
+        Ok(self.received.pop_front().map(|(id, payload)| {
+            self.last_id = Some(id.clone());
+            Delivery {
+                source: format!("{}/{}", self.queue, id),
+                payload,
+            }
+        }))
+    }
+}
+
+impl Subscriber for RedisSubscriber {
+    fn connect(&mut self) -> anyhow::Result<()> {
+        self.con = None;
+        let mut con = connect(&self.uri)?;
+
+        match &self.stream {
+            Some(stream) => {
+                match con.xgroup_create_mkstream::<_, _, _, ()>(&self.queue, &stream.group, "$") {
+                    Ok(_) => info!("已在 {} 上创建消费组 {}", self.queue, stream.group),
+                    Err(e) if e.code() == Some("BUSYGROUP") => {}
+                    Err(e) => return Err(e.into()),
+                }
+                info!(
+                    "已在目标 Redis: {} 上的 {} 流以消费者 {}/{} 进行读取",
+                    self.uri, self.queue, stream.group, stream.consumer
+                );
+            }
+            None => {
+                con.send_packed_command(
+                    &redis::cmd("SUBSCRIBE")
+                        .arg(&self.queue)
+                        .get_packed_command(),
+                )?;
+                con.set_read_timeout(Some(Duration::from_secs(1)))?;
+                info!(
+                    "已在目标 Redis: {} 上的 {} 队列进行订阅",
+                    self.uri, self.queue
+                );
+            }
+        }
+
+        self.pending = true;
+        self.received.clear();
+        self.last_id = None;
+        self.con = Some(con);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> anyhow::Result<Option<Delivery>> {
+        match self.stream.clone() {
+            Some(stream) => self.receive_stream(&stream),
+            None => self.receive_pubsub(),
+        }
+    }
+
+    fn ack(&mut self) -> anyhow::Result<()> {
+        let (Some(stream), Some(id)) = (self.stream.clone(), self.last_id.take()) else {
+            return Ok(());
+        };
+        let queue = self.queue.clone();
+        self.connection()?
+            .xack::<_, _, _, ()>(queue, &stream.group, &[id])?;
+        Ok(())
+    }
+}
+
+pub struct RedisPublisher {
+    uri: String,
+    queue: String,
+    stream: Option<StreamConfig>,
+    con: Option<redis::Connection>,
+}
+
+impl RedisPublisher {
+    pub fn new(uri: &str, queue: &str, stream: Option<StreamConfig>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            queue: queue.to_string(),
+            stream,
+            con: None,
+        }
+    }
+}
+
+impl Publisher for RedisPublisher {
+    fn connect(&mut self) -> anyhow::Result<()> {
+        self.con = Some(connect(&self.uri)?);
+        info!(
+            "已在目标 Redis: {} 上的 {} 队列进行发布循环, 将在收取主线程的数据之后进行发布",
+            self.uri, self.queue
+        );
+        Ok(())
+    }
+
+    fn publish(&mut self, msg: &str) -> anyhow::Result<()> {
+        let con = self
+            .con
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("尚未连接到 Redis"))?;
+        match &self.stream {
+            Some(stream) if stream.max_len > 0 => con.xadd_maxlen::<_, _, _, _, ()>(
+                &self.queue,
+                StreamMaxlen::Approx(stream.max_len),
+                "*",
+                &[(&stream.field, msg)],
+            )?,
+            Some(stream) => {
+                con.xadd::<_, _, _, _, ()>(&self.queue, "*", &[(&stream.field, msg)])?
+            }
+            None => con.publish::<_, _, ()>(&self.queue, msg)?,
+        }
+        Ok(())
+    }
+}